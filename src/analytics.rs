@@ -0,0 +1,90 @@
+use std::{collections::HashMap, f32::consts::PI, fmt};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use crate::Backend;
+
+
+
+// measurements of single channel, distances are in cells and time in units of delta
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub mass: f32,
+    pub centroid: [f32; 3], // centre of mass on torus, x y z
+    pub velocity: [f32; 3], // centroid shift per time unit since previous step
+    pub turn_rate: f32,     // change of heading (direction of velocity) in x-y plane, radians per time unit
+    pub gyration: f32,      // radius of gyration around centroid
+    pub growth: f32,        // sum of positive growth over cells, weighted layer outputs without noise
+}
+impl Stats {
+    pub fn speed(&self) -> f32 {
+        self.velocity.iter().map(|v| v * v ).sum::<f32>().sqrt()
+    }
+
+    // previous - stats of the same channel one step, time units earlier
+    pub fn measure<B: Backend>(b: &B, matrix: &B::Matrix, growth: &B::Matrix, previous: Option<&Stats>, time: f32) -> Self {
+        let dims = b.dims(matrix);
+        let values = b.host(matrix);
+        let mass = values.iter().map(|v| *v as f64 ).sum::<f64>() as f32;
+        let g = b.sum(&b.clamp(growth, 0., f32::INFINITY));
+        let mut stats = Stats { mass, growth: g, ..Default::default() };
+        if mass <= 0. { return stats }
+        let at = |i: usize| [i % dims[0], i / dims[0] % dims[1], i / (dims[0] * dims[1])];
+
+        // circular mean along every axis, so creature crossing the edge isn't split
+        let axes = (0..3).filter(|a| dims[*a] > 1 ).collect::<Vec<_>>();
+        axes.iter().for_each(|a|{
+            let n = dims[*a] as f32;
+            let (s, c) = values.iter().enumerate().fold((0., 0.), |(s, c), (i,v)|{
+                let t = at(i)[*a] as f32 * 2. * PI / n;
+                (s + v * t.sin(), c + v * t.cos())
+            });
+            stats.centroid[*a] = (s.atan2(c) * n / (2. * PI)).rem_euclid(n);
+        });
+
+        let s = values.iter().enumerate().map(|(i,v)|{
+            let p = at(i);
+            v * axes.iter().map(|a|{
+                let n = dims[*a] as f32;
+                let d = p[*a] as f32 - stats.centroid[*a];
+                let d = d - (d / n).round() * n;
+                d * d
+            }).sum::<f32>()
+        }).sum::<f32>();
+        stats.gyration = (s / mass).sqrt();
+
+        if let Some(p) = previous.filter(|p| p.mass > 0. && time > 0. ) {
+            axes.iter().for_each(|a|{
+                let n = dims[*a] as f32;
+                let d = stats.centroid[*a] - p.centroid[*a];
+                stats.velocity[*a] = (d - (d / n).round() * n) / time;
+            });
+            if stats.speed() > 0. && p.speed() > 0. {
+                let turn = stats.velocity[1].atan2(stats.velocity[0]) - p.velocity[1].atan2(p.velocity[0]);
+                stats.turn_rate = ((turn + PI).rem_euclid(2. * PI) - PI) / time;
+            }
+        }
+        stats
+    }
+}
+impl fmt::Debug for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "M={}, C={:?}, V={:?}, W={}, R={}, G={}",
+            self.mass, self.centroid, self.velocity, self.turn_rate, self.gyration, self.growth)
+    }
+}
+
+// stats of every channel, taken by Lenia::evaluate after every step
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Analytics {
+    pub step: u64, // step of the world they were measured at
+    pub channels: HashMap<usize, Stats>,
+}
+impl fmt::Debug for Analytics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut l = format!("Step: {}", self.step);
+        self.channels.iter().sorted_by_key(|(k,_)| **k ).for_each(|(k,s)|{
+            l += &format!("\n{}: {:?}", k, s);
+        });
+        write!(f, "{}", l)
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+
+
+// direction dependent kernel, distance is measured in rotated ellipse and kernel
+// is multiplied by angular harmonic; twisted harmonic makes spiral, chiral kernels
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Anisotropy {
+    pub ratio: f32,     // long to short axis of ellipse, 1 - circle, long axis is kernel radius
+    pub angle: f32,     // orientation of ellipse and harmonic, radians
+    pub order: usize,   // number of harmonic lobes, 0 - none
+    pub amplitude: f32, // strength of harmonic, up to 1 kernel keeps its sign
+    pub twist: f32,     // harmonic phase change between center and edge, radians
+}
+impl Default for Anisotropy {
+    fn default() -> Self {
+        Self { ratio: 1., angle: 0., order: 0, amplitude: 0., twist: 0. }
+    }
+}
+impl Anisotropy {
+    pub fn is_isotropic(&self) -> bool {
+        self.ratio == 1. && (self.order == 0 || self.amplitude == 0.)
+    }
+
+    // x, y - offset from kernel center, in radii
+    pub fn distance(&self, x: f32, y: f32) -> f32 {
+        let (s, c) = self.angle.sin_cos();
+        let u = x * c + y * s;
+        let v = (y * c - x * s) * self.ratio.max(1.);
+        (u * u + v * v).sqrt()
+    }
+
+    // multiplier of kernel value, r - distance from distance()
+    pub fn weight(&self, x: f32, y: f32, r: f32) -> f32 {
+        if self.order == 0 { return 1. }
+        let theta = y.atan2(x) - self.angle - self.twist * r;
+        1. + self.amplitude * (self.order as f32 * theta).cos()
+    }
+
+    // distance of every kernel cell from center (in radii) and its weight,
+    // side is radius*2+1, depth too for 3 dimensions, x changes fastest
+    pub fn lookup(&self, radius: usize, dimensions: usize) -> (Vec<f32>, Vec<f32>) {
+        let h = (radius * 2 + 1) as i64;
+        let r = radius as i64;
+        let depth = if dimensions == 3 { h } else { 1 };
+        let mut distances = vec![0.; (h*h*depth) as usize];
+        let mut weights = vec![1.; (h*h*depth) as usize];
+        let rad = radius as f32;
+        for z in -(depth/2)..=depth/2 {
+            for x in -r..=r {
+                for y in -r..=r {
+                    let (u, v, w) = (x as f32 / rad, y as f32 / rad, z as f32 / rad);
+                    let d = self.distance(u, v);
+                    let d = (d*d + w*w).sqrt();
+                    let i = (((z+depth/2) * h + y + r) * h + (x + r)) as usize;
+                    distances[i] = d;
+                    weights[i] = self.weight(u, v, d);
+                }
+            }
+        }
+        (distances, weights)
+    }
+}
+impl fmt::Debug for Anisotropy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "E={}@{}, H={}x{}, T={}", self.ratio, self.angle, self.order, self.amplitude, self.twist)
+    }
+}
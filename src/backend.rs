@@ -0,0 +1,497 @@
+use std::{cell::Cell, collections::HashMap, f64::consts::PI};
+#[cfg(feature = "arrayfire")]
+use arrayfire::*;
+use crate::{Boundary, Function};
+
+
+
+// state of every channel, by key
+pub type State<B> = HashMap<usize, <B as Backend>::Matrix>;
+
+// numerics used by Lenia, so it can run on ArrayFire or in plain Rust.
+// Matrices are x-y-z volumes (z is 1 for flat worlds), x changes fastest
+pub trait Backend: Clone + Copy + Default {
+    type Matrix: Clone;
+    type Spectrum: Clone;
+    type Random: Clone;
+
+    fn matrix(&self, data: &[f32], dims: [usize; 3]) -> Self::Matrix;
+    fn host(&self, m: &Self::Matrix) -> Vec<f32>;
+    fn dims(&self, m: &Self::Matrix) -> [usize; 3];
+    fn constant(&self, value: f32, dims: [usize; 3]) -> Self::Matrix;
+
+    fn add(&self, a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix;
+    fn sub(&self, a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix;
+    fn mul(&self, a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix;
+    fn max(&self, a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix;
+    fn scale(&self, a: &Self::Matrix, s: f32) -> Self::Matrix;
+    fn offset(&self, a: &Self::Matrix, v: f32) -> Self::Matrix;
+    fn exp(&self, a: &Self::Matrix) -> Self::Matrix;
+    fn abs(&self, a: &Self::Matrix) -> Self::Matrix;
+    fn pow(&self, a: &Self::Matrix, e: f32) -> Self::Matrix;
+    fn clamp(&self, a: &Self::Matrix, low: f32, high: f32) -> Self::Matrix;
+    // 1 where a reaches edge, 0 elsewhere
+    fn step(&self, a: &Self::Matrix, edge: f32) -> Self::Matrix;
+    fn sum(&self, a: &Self::Matrix) -> f32;
+
+    // cell at x gets value of cell at x - offset, wrapping around the edges
+    fn shift(&self, a: &Self::Matrix, offset: [isize; 3]) -> Self::Matrix;
+    // grows every axis longer than 1 by p cells on both sides, filled according to boundary
+    fn pad(&self, a: &Self::Matrix, p: usize, boundary: Boundary) -> Self::Matrix;
+    // removes p cells from both sides of every axis longer than 1
+    fn crop(&self, a: &Self::Matrix, p: usize) -> Self::Matrix;
+    // every axis longer than 1 resized by factor, smooth - bilinear instead of nearest cell
+    fn resample(&self, a: &Self::Matrix, factor: f32, smooth: bool) -> Self::Matrix;
+
+    // fourier transform along first axes (2 or 3)
+    fn transform(&self, a: &Self::Matrix, axes: usize) -> Self::Spectrum;
+    // weighted sum of spectra of the same size
+    fn combine(&self, spectra: &[(&Self::Spectrum, f32)]) -> Self::Spectrum;
+    // product of spectra transformed back, dims - size of transformed matrix
+    fn inverse(&self, a: &Self::Spectrum, kernel: &Self::Spectrum, dims: [usize; 3], axes: usize) -> Self::Matrix;
+    // spectrum of kernel centered at origin of matrix with given dims, kernel
+    // larger than the matrix wraps around it
+    fn prepare(&self, kernel: &Self::Matrix, dims: [usize; 3], axes: usize) -> Self::Spectrum {
+        let placed = place(&self.host(kernel), self.dims(kernel), dims);
+        self.transform(&self.matrix(&placed, dims), axes)
+    }
+    // circular convolution, kernel is centered at its middle cell
+    fn convolve(&self, a: &Self::Matrix, kernel: &Self::Matrix) -> Self::Matrix {
+        let dims = self.dims(a);
+        let axes = if dims[2] > 1 { 3 } else { 2 };
+        self.inverse(&self.transform(a, axes), &self.prepare(kernel, dims, axes), dims, axes)
+    }
+
+    fn function(&self, f: &Function, x: &Self::Matrix) -> Self::Matrix {
+        self.function_mapped(f, x, &HashMap::new())
+    }
+    // parameters present in maps vary across space instead of being constant
+    fn function_mapped(&self, f: &Function, x: &Self::Matrix, maps: &HashMap<usize, Self::Matrix>) -> Self::Matrix;
+
+    // random sequence, the same for the same seed
+    fn random(&self, seed: u64) -> Self::Random;
+    fn uniform(&self, random: &Self::Random, dims: [usize; 3]) -> Self::Matrix;
+    fn normal(&self, random: &Self::Random, dims: [usize; 3]) -> Self::Matrix;
+}
+
+// kernel cells moved to offsets from the origin, wrapped around the world
+fn place(kernel: &[f32], k: [usize; 3], n: [usize; 3]) -> Vec<f32> {
+    let mut placed = vec![0_f32; n.iter().product()];
+    kernel.iter().enumerate().for_each(|(j,v)|{
+        let at = [j % k[0], j / k[0] % k[1], j / (k[0] * k[1])];
+        let p = [0, 1, 2].map(|d| (at[d] as isize - (k[d] / 2) as isize).rem_euclid(n[d] as isize) as usize );
+        placed[(p[2] * n[1] + p[1]) * n[0] + p[0]] += v;
+    });
+    placed
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    pub data: Vec<f32>,
+    pub dims: [usize; 3],
+}
+
+// complex values of transformed grid, of the same size
+#[derive(Clone, PartialEq, Debug)]
+pub struct Spectrum {
+    pub data: Vec<(f64, f64)>,
+    pub dims: [usize; 3],
+}
+
+// state of xorshift generator
+#[derive(Clone, PartialEq, Debug)]
+pub struct Xorshift(Cell<u64>);
+impl Xorshift {
+    fn next(&self) -> u64 {
+        let mut x = self.0.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0.set(x);
+        x
+    }
+    // uniform in 0..1
+    fn float(&self) -> f32 {
+        (self.next() >> 40) as f32 / (1 << 24) as f32
+    }
+}
+
+// plain Rust, convolution goes through FFT of any size
+#[derive(Clone, Copy, Default)]
+pub struct Cpu;
+impl Cpu {
+    fn map(a: &Grid, f: impl Fn(f32) -> f32) -> Grid {
+        Grid { data: a.data.iter().map(|v| f(*v) ).collect(), dims: a.dims }
+    }
+    fn zip(a: &Grid, b: &Grid, f: impl Fn(f32, f32) -> f32) -> Grid {
+        assert_eq!(a.dims, b.dims, "matrices of different size");
+        Grid { data: a.data.iter().zip(b.data.iter()).map(|(x,y)| f(*x, *y) ).collect(), dims: a.dims }
+    }
+    // axes longer than 1 are grown by p, source gives value at position in a
+    fn resize(a: &Grid, p: isize, source: impl Fn([isize; 3]) -> f32) -> Grid {
+        let dims = a.dims.map(|n| if n > 1 { (n as isize + 2 * p) as usize } else { n } );
+        let shift = a.dims.map(|n| if n > 1 { p } else { 0 } );
+        let mut data = Vec::with_capacity(dims.iter().product());
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    data.push(source([x as isize - shift[0], y as isize - shift[1], z as isize - shift[2]]));
+                }
+            }
+        }
+        Grid { data, dims }
+    }
+}
+impl Backend for Cpu {
+    type Matrix = Grid;
+    type Spectrum = Spectrum;
+    type Random = Xorshift;
+
+    fn matrix(&self, data: &[f32], dims: [usize; 3]) -> Grid {
+        assert_eq!(data.len(), dims.iter().product::<usize>(), "data doesn't match dims");
+        Grid { data: data.to_vec(), dims }
+    }
+    fn host(&self, m: &Grid) -> Vec<f32> { m.data.clone() }
+    fn dims(&self, m: &Grid) -> [usize; 3] { m.dims }
+    fn constant(&self, value: f32, dims: [usize; 3]) -> Grid {
+        Grid { data: vec![value; dims.iter().product()], dims }
+    }
+
+    fn add(&self, a: &Grid, b: &Grid) -> Grid { Cpu::zip(a, b, |x, y| x + y ) }
+    fn sub(&self, a: &Grid, b: &Grid) -> Grid { Cpu::zip(a, b, |x, y| x - y ) }
+    fn mul(&self, a: &Grid, b: &Grid) -> Grid { Cpu::zip(a, b, |x, y| x * y ) }
+    fn max(&self, a: &Grid, b: &Grid) -> Grid { Cpu::zip(a, b, f32::max) }
+    fn scale(&self, a: &Grid, s: f32) -> Grid { Cpu::map(a, |x| x * s ) }
+    fn offset(&self, a: &Grid, v: f32) -> Grid { Cpu::map(a, |x| x + v ) }
+    fn exp(&self, a: &Grid) -> Grid { Cpu::map(a, f32::exp) }
+    fn abs(&self, a: &Grid) -> Grid { Cpu::map(a, f32::abs) }
+    fn pow(&self, a: &Grid, e: f32) -> Grid { Cpu::map(a, |x| x.powf(e) ) }
+    fn clamp(&self, a: &Grid, low: f32, high: f32) -> Grid { Cpu::map(a, |x| x.clamp(low, high) ) }
+    fn step(&self, a: &Grid, edge: f32) -> Grid { Cpu::map(a, |x| if x >= edge { 1. } else { 0. } ) }
+    fn sum(&self, a: &Grid) -> f32 { a.data.iter().map(|v| *v as f64 ).sum::<f64>() as f32 }
+
+    fn shift(&self, a: &Grid, offset: [isize; 3]) -> Grid {
+        let n = a.dims.map(|n| n as isize );
+        Cpu::resize(a, 0, |at|{
+            let i = [0, 1, 2].map(|d| (at[d] - offset[d]).rem_euclid(n[d]) );
+            a.data[((i[2] * n[1] + i[1]) * n[0] + i[0]) as usize]
+        })
+    }
+
+    fn pad(&self, a: &Grid, p: usize, boundary: Boundary) -> Grid {
+        let n = a.dims.map(|n| n as isize );
+        Cpu::resize(a, p as isize, |at|{
+            let mut i = [0; 3];
+            for d in 0..3 {
+                let (x, n) = (at[d], n[d]);
+                i[d] = match boundary {
+                    Boundary::Periodic => x.rem_euclid(n),
+                    Boundary::Zero if x < 0 || x >= n => return 0.,
+                    Boundary::Zero => x,
+                    Boundary::Clamp => x.clamp(0, n - 1),
+                    // edge cell is repeated, like arrayfire's symmetric border
+                    Boundary::Reflect => {
+                        let m = x.rem_euclid(2 * n);
+                        if m < n { m } else { 2 * n - m - 1 }
+                    },
+                };
+            }
+            a.data[((i[2] * n[1] + i[1]) * n[0] + i[0]) as usize]
+        })
+    }
+
+    fn crop(&self, a: &Grid, p: usize) -> Grid {
+        let n = a.dims.map(|n| n as isize );
+        Cpu::resize(a, -(p as isize), |i| a.data[((i[2] * n[1] + i[1]) * n[0] + i[0]) as usize] )
+    }
+    fn resample(&self, a: &Grid, factor: f32, smooth: bool) -> Grid {
+        let n = a.dims;
+        let dims = n.map(|n| if n > 1 { ((n as f32 * factor).round() as usize).max(1) } else { n } );
+        // source cells along axis d with their weights, centres of first and last cells are kept
+        let taps = |d: usize, o: usize| -> Vec<(usize, f32)> {
+            let s = ((o as f32 + 0.5) * n[d] as f32 / dims[d] as f32 - 0.5).clamp(0., (n[d] - 1) as f32);
+            if !smooth { return vec![(s.round() as usize, 1.)] }
+            let i = s.floor() as usize;
+            vec![(i, 1. - (s - i as f32)), ((i + 1).min(n[d] - 1), s - i as f32)]
+        };
+        let mut data = Vec::with_capacity(dims.iter().product());
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    let v = taps(2, z).iter().map(|(k,wz)|{
+                        taps(1, y).iter().map(|(j,wy)|{
+                            taps(0, x).iter().map(|(i,wx)| a.data[(k * n[1] + j) * n[0] + i] * wx ).sum::<f32>() * wy
+                        }).sum::<f32>() * wz
+                    }).sum();
+                    data.push(v);
+                }
+            }
+        }
+        Grid { data, dims }
+    }
+
+    fn transform(&self, a: &Grid, axes: usize) -> Spectrum {
+        let mut data = a.data.iter().map(|v| (*v as f64, 0.) ).collect::<Vec<_>>();
+        fft(&mut data, a.dims, axes, false);
+        Spectrum { data, dims: a.dims }
+    }
+    fn combine(&self, spectra: &[(&Spectrum, f32)]) -> Spectrum {
+        let (first, _) = spectra[0];
+        let mut data = vec![(0., 0.); first.data.len()];
+        spectra.iter().for_each(|(s,w)|{
+            assert_eq!(s.dims, first.dims, "spectra of different size");
+            let w = *w as f64;
+            data.iter_mut().zip(s.data.iter()).for_each(|(d,v)| *d = (d.0 + v.0 * w, d.1 + v.1 * w) );
+        });
+        Spectrum { data, dims: first.dims }
+    }
+    fn inverse(&self, a: &Spectrum, kernel: &Spectrum, dims: [usize; 3], axes: usize) -> Grid {
+        assert_eq!(a.dims, kernel.dims, "spectra of different size");
+        let mut data = a.data.iter().zip(kernel.data.iter()).map(|(s,k)| cmul(*s, *k) ).collect::<Vec<_>>();
+        fft(&mut data, dims, axes, true);
+        let n = dims.iter().take(axes).product::<usize>() as f64;
+        Grid { data: data.iter().map(|v| (v.0 / n) as f32 ).collect(), dims }
+    }
+
+    fn function_mapped(&self, f: &Function, x: &Grid, maps: &HashMap<usize, Grid>) -> Grid {
+        if maps.is_empty() { return Cpu::map(x, |v| f._calc(v) ) }
+        let mut parameters = f.parameters.clone();
+        // maps of parameters the function doesn't have are left out, as on arrayfire
+        let maps = maps.iter().filter(|(p,_)| **p < parameters.len() ).collect::<Vec<_>>();
+        let data = x.data.iter().enumerate().map(|(i,v)|{
+            maps.iter().for_each(|(p,m)| parameters[**p] = m.data[i] );
+            f.calc_with(*v, &parameters)
+        }).collect();
+        Grid { data, dims: x.dims }
+    }
+
+    fn random(&self, seed: u64) -> Xorshift {
+        // splitmix step, so that close seeds give unrelated sequences and 0 isn't stuck
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Xorshift(Cell::new((z ^ (z >> 31)).max(1)))
+    }
+    fn uniform(&self, random: &Xorshift, dims: [usize; 3]) -> Grid {
+        Grid { data: (0..dims.iter().product()).map(|_| random.float() ).collect(), dims }
+    }
+    // Box-Muller
+    fn normal(&self, random: &Xorshift, dims: [usize; 3]) -> Grid {
+        let data = (0..dims.iter().product()).map(|_|{
+            let u = 1. - random.float();
+            let v = random.float();
+            (-2. * u.ln()).sqrt() * (2. * std::f32::consts::PI * v).cos()
+        }).collect();
+        Grid { data, dims }
+    }
+}
+
+// in place transform along first axes of any length, inverse isn't scaled
+fn fft(data: &mut [(f64, f64)], dims: [usize; 3], axes: usize, inverse: bool) {
+    let stride = [1, dims[0], dims[0] * dims[1]];
+    (0..axes.min(3)).filter(|d| dims[*d] > 1 ).for_each(|d|{
+        let len = dims[d];
+        let plan = Plan::new(len, inverse);
+        let mut line = vec![(0., 0.); len];
+        (0..data.len()).filter(|i| (i / stride[d]).is_multiple_of(len) ).for_each(|start|{
+            (0..len).for_each(|i| line[i] = data[start + i * stride[d]] );
+            plan.run(&mut line);
+            (0..len).for_each(|i| data[start + i * stride[d]] = line[i] );
+        });
+    });
+}
+
+fn cmul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+// transform of single length, powers of 2 directly, others through Bluestein's
+// chirp z-transform, which is a convolution of power of 2 length
+struct Plan {
+    len: usize,
+    inverse: bool,
+    chirp: Vec<(f64, f64)>,    // exp(-+i*pi*k^2/n)
+    spectrum: Vec<(f64, f64)>, // transformed conjugate chirp, padded
+}
+impl Plan {
+    fn new(len: usize, inverse: bool) -> Self {
+        if len.is_power_of_two() { return Self { len, inverse, chirp: vec![], spectrum: vec![] } }
+        let sign = if inverse { 1. } else { -1. };
+        // k^2 is taken modulo 2n, so that angle stays precise for long lines
+        let chirp = (0..len).map(|k|{
+            let a = sign * PI * ((k * k) % (2 * len)) as f64 / len as f64;
+            (a.cos(), a.sin())
+        }).collect::<Vec<_>>();
+        let m = (2 * len - 1).next_power_of_two();
+        let mut spectrum = vec![(0., 0.); m];
+        (0..len).for_each(|k|{
+            let c = (chirp[k].0, -chirp[k].1);
+            spectrum[k] = c;
+            if k > 0 { spectrum[m - k] = c; }
+        });
+        radix2(&mut spectrum, false);
+        Self { len, inverse, chirp, spectrum }
+    }
+
+    fn run(&self, line: &mut [(f64, f64)]) {
+        if self.chirp.is_empty() { return radix2(line, self.inverse) }
+        let m = self.spectrum.len();
+        let mut a = vec![(0., 0.); m];
+        (0..self.len).for_each(|k| a[k] = cmul(line[k], self.chirp[k]) );
+        radix2(&mut a, false);
+        a.iter_mut().zip(self.spectrum.iter()).for_each(|(x,s)| *x = cmul(*x, *s) );
+        radix2(&mut a, true);
+        (0..self.len).for_each(|k|{
+            let v = cmul(a[k], self.chirp[k]);
+            line[k] = (v.0 / m as f64, v.1 / m as f64);
+        });
+    }
+}
+
+// iterative radix 2
+fn radix2(line: &mut [(f64, f64)], inverse: bool) {
+    let n = line.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j { line.swap(i, j); }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = 2. * PI / len as f64 * if inverse { 1. } else { -1. };
+        let w = (angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let mut c = (1., 0.);
+            for k in 0..len / 2 {
+                let u = line[start + k];
+                let v = cmul(line[start + k + len / 2], c);
+                line[start + k] = (u.0 + v.0, u.1 + v.1);
+                line[start + k + len / 2] = (u.0 - v.0, u.1 - v.1);
+                c = cmul(c, w);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+
+// same numerics as Cpu on the gpu
+#[cfg(feature = "arrayfire")]
+#[derive(Clone, Copy, Default)]
+pub struct ArrayFire;
+#[cfg(feature = "arrayfire")]
+impl ArrayFire {
+    fn dim4(dims: [usize; 3]) -> Dim4 {
+        Dim4::new(&[dims[0] as u64, dims[1] as u64, dims[2] as u64, 1])
+    }
+}
+#[cfg(feature = "arrayfire")]
+impl Backend for ArrayFire {
+    type Matrix = Array<f32>;
+    type Spectrum = Array<c32>;
+    type Random = RandomEngine;
+
+    fn matrix(&self, data: &[f32], dims: [usize; 3]) -> Array<f32> {
+        Array::new(data, ArrayFire::dim4(dims))
+    }
+    fn host(&self, m: &Array<f32>) -> Vec<f32> {
+        let mut data = vec![0_f32; m.elements()];
+        m.host(&mut data);
+        data
+    }
+    fn dims(&self, m: &Array<f32>) -> [usize; 3] {
+        let d = m.dims();
+        [d[0] as usize, d[1] as usize, d[2] as usize]
+    }
+    fn constant(&self, value: f32, dims: [usize; 3]) -> Array<f32> {
+        constant(value, ArrayFire::dim4(dims))
+    }
+
+    fn add(&self, a: &Array<f32>, b: &Array<f32>) -> Array<f32> { a + b }
+    fn sub(&self, a: &Array<f32>, b: &Array<f32>) -> Array<f32> { a - b }
+    fn mul(&self, a: &Array<f32>, b: &Array<f32>) -> Array<f32> { a * b }
+    fn max(&self, a: &Array<f32>, b: &Array<f32>) -> Array<f32> { maxof(a, b, false) }
+    fn scale(&self, a: &Array<f32>, s: f32) -> Array<f32> { a * s }
+    fn offset(&self, a: &Array<f32>, v: f32) -> Array<f32> { a + v }
+    fn exp(&self, a: &Array<f32>) -> Array<f32> { exp(a) }
+    fn abs(&self, a: &Array<f32>) -> Array<f32> { abs(a) }
+    fn pow(&self, a: &Array<f32>, e: f32) -> Array<f32> { pow(a, &e, false) }
+    fn clamp(&self, a: &Array<f32>, low: f32, high: f32) -> Array<f32> { clamp(a, &low, &high, false) }
+    fn step(&self, a: &Array<f32>, edge: f32) -> Array<f32> { ge(a, &edge, false).cast::<f32>() }
+    fn sum(&self, a: &Array<f32>) -> f32 { sum_all(a).0 }
+
+    fn shift(&self, a: &Array<f32>, offset: [isize; 3]) -> Array<f32> {
+        shift(a, &[offset[0] as i32, offset[1] as i32, offset[2] as i32, 0])
+    }
+    fn pad(&self, a: &Array<f32>, p: usize, boundary: Boundary) -> Array<f32> {
+        let d = a.dims();
+        let p = [0, 1, 2, 3].map(|i| if i < 3 && d[i] > 1 { p as u64 } else { 0 } );
+        pad(a, Dim4::new(&p), Dim4::new(&p), boundary.border_type())
+    }
+    fn crop(&self, a: &Array<f32>, p: usize) -> Array<f32> {
+        let d = a.dims();
+        let seqs = (0..3).map(|i|{
+            let p = if d[i] > 1 { p as u32 } else { 0 };
+            Seq::new(p, d[i] as u32 - p - 1, 1)
+        }).collect::<Vec<_>>();
+        index(a, &seqs).copy()
+    }
+    fn resample(&self, a: &Array<f32>, factor: f32, smooth: bool) -> Array<f32> {
+        let method = if smooth { InterpType::BILINEAR } else { InterpType::NEAREST };
+        let d = a.dims();
+        let size = |n: u64| ((n as f32 * factor).round() as i64).max(1);
+        let t = resize(a, size(d[0]), size(d[1]), method);
+        if d[2] == 1 { return t }
+        // depth is brought to front, resized and put back
+        let t = reorder_v2(&t, 2, 0, Some(vec![1, 3]));
+        let t = resize(&t, size(d[2]), t.dims()[1] as i64, method);
+        reorder_v2(&t, 1, 2, Some(vec![0, 3]))
+    }
+
+    // worlds stacked along 4th dimension are transformed one by one
+    fn transform(&self, a: &Array<f32>, axes: usize) -> Array<c32> {
+        if axes == 3 { fft3_r2c(a, 1., 0, 0, 0) } else { fft2_r2c(a, 1., 0, 0) }
+    }
+    fn combine(&self, spectra: &[(&Array<c32>, f32)]) -> Array<c32> {
+        spectra.iter().map(|(s,w)| *s * *w ).reduce(|a,b| a + b ).unwrap()
+    }
+    // stacked spectra can share single kernel spectrum or have one each
+    fn inverse(&self, a: &Array<c32>, kernel: &Array<c32>, dims: [usize; 3], axes: usize) -> Array<f32> {
+        let n = dims.iter().take(axes).product::<usize>() as f64;
+        let odd = dims[0] % 2 == 1;
+        let t = mul(a, kernel, true);
+        if axes == 3 { fft3_c2r(&t, 1. / n, odd) } else { fft2_c2r(&t, 1. / n, odd) }
+    }
+    fn prepare(&self, kernel: &Array<f32>, dims: [usize; 3], axes: usize) -> Array<c32> {
+        let k = self.dims(kernel);
+        if (0..3).any(|i| k[i] > dims[i] ) {
+            let placed = place(&self.host(kernel), k, dims);
+            return self.transform(&self.matrix(&placed, dims), axes)
+        }
+        // kernel centered at origin of world sized array
+        let end = [0, 1, 2, 3].map(|i| if i < 3 { (dims[i] - k[i]) as u64 } else { 0 } );
+        let offset = [0, 1, 2, 3].map(|i| if i < 3 { -((k[i] / 2) as i32) } else { 0 } );
+        let temp = pad(kernel, Dim4::new(&[0; 4]), Dim4::new(&end), BorderType::ZERO);
+        self.transform(&shift(&temp, &offset), axes)
+    }
+
+    fn function_mapped(&self, f: &Function, x: &Array<f32>, maps: &HashMap<usize, Array<f32>>) -> Array<f32> {
+        f.calc_array_mapped(x, maps)
+    }
+
+    fn random(&self, seed: u64) -> RandomEngine {
+        RandomEngine::new(RandomEngineType::PHILOX_4X32_10, Some(seed))
+    }
+    fn uniform(&self, random: &RandomEngine, dims: [usize; 3]) -> Array<f32> {
+        random_uniform::<f32>(ArrayFire::dim4(dims), random)
+    }
+    fn normal(&self, random: &RandomEngine, dims: [usize; 3]) -> Array<f32> {
+        random_normal::<f32>(ArrayFire::dim4(dims), random)
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use arrayfire::*;
+use itertools::Itertools;
+use crate::{ArrayFire, Backend, Boundary, Cause, DataLenia, Flow, Integrator, Layer, Lenia, Mix, Noise, PresetError};
+
+
+
+// Many independent worlds evaluated together, their states are stacked along 4th dimension.
+// Worlds have to share world size, integrator with flow settings, channel and layer keys,
+// and layer sources, mix, boundary, dimensions and padding; functions, weights, delta and
+// radii that keep the padding can differ. Worlds with walls or noise are refused.
+pub struct Batch {
+    pub worlds: Vec<Lenia<ArrayFire>>, // parameters, matrices are up to date only after world()
+    pub channels: HashMap<usize, Array<f32>>, // stacked states
+    pub fitness: Vec<f32>,
+    kernels: HashMap<usize, Array<c32>>, // layer key, shared or stacked kernel spectra
+    deltas: Array<f32>,
+}
+impl Batch {
+    pub fn new(mut worlds: Vec<Lenia<ArrayFire>>) -> Result<Self, String> {
+        Self::check(&worlds)?;
+        worlds.iter_mut().for_each(|w| w.init() );
+        let channels = worlds[0].channels.keys().map(|k|{
+            (*k, stack( worlds.iter().map(|w| w.channels.get(k).unwrap().matrix.clone() ).collect() ))
+        }).collect();
+        let deltas = worlds.iter().map(|w| w.delta ).collect::<Vec<_>>();
+        let deltas = Array::new(&deltas, Dim4::new(&[1, 1, 1, worlds.len() as u64]));
+
+        let mut batch = Self {
+            fitness: vec![0.; worlds.len()],
+            worlds, channels,
+            kernels: HashMap::new(),
+            deltas,
+        };
+        batch.prepare();
+        Ok(batch)
+    }
+
+    pub fn load(dirs: &[String]) -> Result<Self, PresetError> {
+        Self::new( dirs.iter().map(|d| DataLenia::load(d) ).collect::<Result<_, _>>()? )
+            .map_err(|e| PresetError::new(&dirs.join(", "), Cause::Invalid(e)) )
+    }
+
+    // worlds that can't be stacked, every one is compared with the first
+    fn check(worlds: &[Lenia<ArrayFire>]) -> Result<(), String> {
+        let Some(first) = worlds.first() else { return Err("batch has no worlds".to_string()) };
+        let dims = |w: &Lenia<ArrayFire>| w.channels.values().next().map(|ch| ArrayFire.dims(&ch.matrix) );
+        let keys = |m: &HashMap<usize, f32>| m.keys().sorted().copied().collect::<Vec<_>>();
+        worlds.iter().enumerate().try_for_each(|(i,w)|{
+            let problems = w.validate();
+            if !problems.is_empty() { return Err(format!("world {}: {}", i, problems.iter().join(", "))) }
+            if w.walls.is_some() || w.channels.values().any(|ch| ch.walls.is_some() ) { return Err(format!("world {} has walls", i)) }
+            if w.noise != Noise::default() { return Err(format!("world {} has noise", i)) }
+            let differs = |what: &str| Err(format!("world {} differs from world 0 in {}", i, what));
+            let Some(d) = dims(w) else { return differs("channels") };
+            if Some(d) != dims(first) { return differs("world size") }
+            if w.integrator != first.integrator || w.flow != first.flow { return differs("integrator") }
+            if w.channels.keys().sorted().ne(first.channels.keys().sorted()) { return differs("channels") }
+            if w.layers.keys().sorted().ne(first.layers.keys().sorted()) { return differs("layers") }
+            if first.channels.iter().any(|(k,ch)| keys(&w.channels[k].weights) != keys(&ch.weights) ) { return differs("channel weights") }
+            first.layers.iter().sorted_by_key(|(k,_)| **k ).try_for_each(|(k,l)|{
+                let m = &w.layers[k];
+                let inputs = |l: &Layer<ArrayFire>| l.inputs().iter().map(|(c,_)| *c ).collect::<Vec<_>>();
+                if inputs(m) != inputs(l) { return differs(&format!("sources of layer {}", k)) }
+                if m.mix != l.mix || m.boundary != l.boundary || m.dimensions != l.dimensions {
+                    return differs(&format!("mix, boundary or dimensions of layer {}", k))
+                }
+                // kernels of different size can only be stacked when no world pads for them
+                if m.padding(d) != l.padding(d) { return differs(&format!("padding of layer {} (radius {} and {})", k, m.radius, l.radius)) }
+                Ok(())
+            })
+        })
+    }
+
+    pub fn len(&self) -> usize { self.worlds.len() }
+    pub fn is_empty(&self) -> bool { self.worlds.is_empty() }
+
+    // copy state of single world back into its Lenia
+    pub fn world(&mut self, i: usize) -> &Lenia<ArrayFire> {
+        self.channels.iter().for_each(|(k,m)|{
+            self.worlds[i].channels.get_mut(k).unwrap().matrix = unstack(m, i);
+        });
+        self.worlds[i].fitness = self.fitness[i];
+        &self.worlds[i]
+    }
+
+    pub fn save(&mut self, i: usize, dir: &str) -> Result<(), PresetError> {
+        DataLenia::save(dir, self.world(i))
+    }
+
+    // kernel spectra, computed once for worlds with matching layers
+    fn prepare(&mut self) {
+        self.kernels = self.worlds[0].layers.iter().map(|(k,l)|{
+            let shared = self.worlds.iter().all(|w| w.layers.get(k).unwrap() == l );
+            let spectrum = if shared { l.kernel_fft.clone().unwrap() } else {
+                stack( self.worlds.iter().map(|w| w.layers.get(k).unwrap().kernel_fft.clone().unwrap() ).collect() )
+            };
+            (*k, spectrum)
+        }).collect();
+    }
+
+    // weighted growth of every channel for given stacked state
+    fn rates(&self, state: &HashMap<usize, Array<f32>>) -> HashMap<usize, Array<f32>> {
+        let mut spectra: HashMap<(usize, Boundary, usize, usize), Array<c32>> = HashMap::new();
+        let mut outputs: HashMap<usize, Array<f32>> = HashMap::new();
+        self.worlds[0].layers.iter().for_each(|(k,l)|{
+            let inputs = l.inputs();
+            let dims = ArrayFire.dims(state.get(&inputs[0].0).unwrap());
+            inputs.iter().for_each(|(c,_)|{
+                spectra.entry(l.spectrum_key(*c, dims)).or_insert_with(|| l.transform(&ArrayFire, state.get(c).unwrap()) );
+            });
+            let kernel = self.kernels.get(k).unwrap();
+
+            let growth = &l.growth_map;
+            let shared = self.worlds.iter().all(|w|{
+                let l = w.layers.get(k).unwrap();
+                l.growth_map == *growth && l.parameter_maps.is_empty()
+            });
+            let grow = |t: Array<f32>| if shared { growth.calc_array(&t) } else {
+                stack( self.worlds.iter().enumerate().map(|(i,w)|{
+                    let l = w.layers.get(k).unwrap();
+                    l.growth_map.calc_array_mapped(&unstack(&t, i), &l.parameter_maps)
+                }).collect() )
+            };
+            // input weights of every world
+            let input = inputs.iter().map(|(c,_)|{
+                let w = self.worlds.iter().map(|w|{
+                    w.layers.get(k).unwrap().inputs().iter().find(|(s,_)| s == c ).map_or(0., |(_,w)| *w )
+                }).collect::<Vec<_>>();
+                (spectra.get(&l.spectrum_key(*c, dims)).unwrap(), Array::new(&w, Dim4::new(&[1, 1, 1, self.len() as u64])))
+            }).collect::<Vec<_>>();
+
+            let out = match l.mix {
+                Mix::Input => {
+                    let spectrum = input.iter().map(|(s,w)| mul(*s, w, true) ).reduce(|a,b| a + b ).unwrap();
+                    grow(l.convolve(&ArrayFire, &spectrum, kernel, dims))
+                },
+                Mix::Output => input.iter().map(|(s,w)|{
+                    mul(&grow(l.convolve(&ArrayFire, s, kernel, dims)), w, true)
+                }).reduce(|a,b| a + b ).unwrap(),
+            };
+            outputs.insert(*k, out);
+        });
+
+        state.iter().map(|(c,m)|{
+            let ch = self.worlds[0].channels.get(c).unwrap();
+            let sum = ch.weights.keys().fold(constant(0_f32, m.dims()), |sum, k|{
+                let w = self.worlds.iter().map(|w| *w.channels.get(c).unwrap().weights.get(k).unwrap() ).collect::<Vec<_>>();
+                let w = Array::new(&w, Dim4::new(&[1, 1, 1, self.len() as u64]));
+                sum + mul(outputs.get(k).unwrap(), &w, true)
+            });
+            (*c, sum)
+        }).collect()
+    }
+
+    // state + h*delta*rate
+    fn step(&self, state: &HashMap<usize, Array<f32>>, rates: &HashMap<usize, Array<f32>>, h: f32) -> HashMap<usize, Array<f32>> {
+        state.iter().map(|(k,m)|{
+            let t = mul(rates.get(k).unwrap(), &self.deltas, true) * h;
+            (*k, clamp(&(m + t), &0_f32, &1_f32, false))
+        }).collect()
+    }
+
+    pub fn evaluate(&mut self) {
+        let b = &ArrayFire;
+        let base = self.channels.clone();
+        let integrator = self.worlds[0].integrator;
+        let rate = integrator.rate(b, &base, |state| self.rates(state), |rate, h| self.step(&base, rate, h) );
+        self.channels = match integrator {
+            Integrator::Flow => {
+                let flow = self.worlds[0].flow;
+                let dims = base.values().next().unwrap().dims();
+                let n = if dims[2] > 1 { 3 } else { 2 };
+                let total = base.values().fold(constant(0_f32, dims), |t, m| t + m );
+                let alpha = flow.alpha(b, &total);
+                // delta differs between worlds, so it's applied to gradients
+                let total = Flow::gradient(b, &total, n).iter().map(|g| mul(g, &self.deltas, true) ).collect::<Vec<_>>();
+                base.iter().map(|(c,m)|{
+                    let affinity = Flow::gradient(b, rate.get(c).unwrap(), n).iter()
+                        .map(|g| mul(g, &self.deltas, true) ).collect::<Vec<_>>();
+                    let displacement = flow.displacement(b, &affinity, &total, &alpha, 1.);
+                    (*c, flow.reintegrate(b, m, &displacement, None))
+                }).collect()
+            },
+            _ => self.step(&base, &rate, 1.),
+        };
+
+        //// calculate fitness per world
+        self.fitness = vec![0.; self.len()];
+        self.channels.values().for_each(|m|{
+            let d = m.dims();
+            let flat = moddims(m, Dim4::new(&[d[0]*d[1]*d[2], d[3], 1, 1]));
+            let mut means = vec![0_f32; d[3] as usize];
+            mean(&flat, 0).host(&mut means);
+            self.fitness.iter_mut().zip(means.iter()).for_each(|(f,m)| *f += m );
+        });
+        let n = self.channels.len() as f32;
+        self.fitness.iter_mut().for_each(|f| *f /= n );
+    }
+}
+
+// join along 4th dimension, arrayfire takes at most 10 arrays at once
+fn stack<T: HasAfEnum>(mut arrays: Vec<Array<T>>) -> Array<T> {
+    while arrays.len() > 1 {
+        arrays = arrays.chunks(10).map(|c| join_many(3, c.iter().collect()) ).collect();
+    }
+    arrays.pop().unwrap()
+}
+
+fn unstack<T: HasAfEnum>(stacked: &Array<T>, i: usize) -> Array<T> {
+    let d = stacked.dims();
+    let seqs = [
+        Seq::new(0, d[0] as u32 - 1, 1),
+        Seq::new(0, d[1] as u32 - 1, 1),
+        Seq::new(0, d[2] as u32 - 1, 1),
+        Seq::new(i as u32, i as u32, 1),
+    ];
+    index(stacked, &seqs).copy()
+}
@@ -1,21 +1,33 @@
 use std::{collections::HashMap, fmt};
+#[cfg(feature = "arrayfire")]
+use std::fs;
+#[cfg(feature = "arrayfire")]
 use arrayfire::*;
-use serde::{Deserialize, Serialize};
-use crate::Function;
+use itertools::Itertools;
+use crate::{Anisotropy, Backend, Boundary, Function, Mix};
+#[cfg(feature = "arrayfire")]
+use crate::{ArrayFire, Cause, PresetError, preset::read_bin};
 
 
 
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Layer {
+#[derive(Clone)]
+pub struct Layer<B: Backend> {
     pub kernel: Function,
-    pub kernel_lookup: Array<f32>,
+    pub kernel_lookup: B::Matrix,
+    pub kernel_fft: Option<B::Spectrum>,
+    pub fft_dims: [usize; 3], // world size kernel_fft was computed for
     pub growth_map: Function,
+    pub parameter_maps: HashMap<usize, B::Matrix>, // growth parameter index, value for every cell
     pub source_key: usize, // number of channel that will be used as input
-    pub matrix_out: Array<f32>,
+    pub sources: HashMap<usize, f32>, // channel key, weight; when not empty used instead of source_key
+    pub mix: Mix,
     pub radius: usize,
+    pub boundary: Boundary,
+    pub dimensions: usize, // 2 - flat world, 3 - volume with spherical kernel
+    pub anisotropy: Anisotropy, // applied in x-y plane
 }
-impl Layer {
+impl<B: Backend> Layer<B> {
     pub fn new(
         kernel: Function,
         growth_map: Function,
@@ -24,105 +36,191 @@ impl Layer {
     ) -> Self {
         Layer { 
             kernel, 
-            kernel_lookup: Array::<f32>::new_empty( Dim4::new(&[radius as u64*2+1, radius as u64*2+1, 1, 1]) ),
-            growth_map, source_key, matrix_out: Array::<f32>::new_empty(Dim4::new(&[512, 512, 1, 1])), radius,
+            kernel_lookup: B::default().constant(0., [radius*2+1, radius*2+1, 1]),
+            kernel_fft: None,
+            fft_dims: [0; 3],
+            growth_map, parameter_maps: HashMap::new(), source_key, 
+            sources: HashMap::new(), mix: Mix::Input, radius,
+            boundary: Boundary::Periodic,
+            dimensions: 2,
+            anisotropy: Anisotropy::default(),
         }
     }
 
-    pub fn generate_kernel_lookup(&mut self) {
-        let mut kernel_lookup = vec![0.; (self.radius*2+1)*(self.radius*2+1)];
-        let h = (self.radius * 2 + 1) as i64;
-        for x in -(self.radius as i64)..=self.radius as i64 {
-            for y in -(self.radius as i64)..=self.radius as i64 {
-                let r = ( (x*x+y*y) as f32 ).sqrt();
-                kernel_lookup[((y+self.radius as i64) * h + (x + self.radius as i64)) as usize] 
-                    = r/self.radius as f32;
-            }    
+    pub fn generate_kernel_lookup(&mut self, b: &B) {
+        let h = self.radius * 2 + 1;
+        let depth = if self.dimensions == 3 { h } else { 1 };
+        let (kernel_lookup, weights) = self.anisotropy.lookup(self.radius, self.dimensions);
+        let dims = [h, h, depth];
+        self.kernel_lookup = b.function(&self.kernel, &b.matrix(&kernel_lookup, dims));
+        if !self.anisotropy.is_isotropic() {
+            self.kernel_lookup = b.mul(&self.kernel_lookup, &b.matrix(&weights, dims));
         }
-        self.kernel_lookup = Array::<f32>::new(&kernel_lookup, 
-            Dim4::new(&[self.radius as u64 *2+1, self.radius as u64 *2+1, 1, 1]));
-        self.kernel_lookup = self.kernel.calc_array(&self.kernel_lookup);
-        let (sum, _) = sum_all(&self.kernel_lookup);
-        self.kernel_lookup = div(&self.kernel_lookup, &sum, false);
+        let sum = b.sum(&self.kernel_lookup);
+        self.kernel_lookup = b.scale(&self.kernel_lookup, 1. / sum);
         // convolution will always be equal to 1
+        if self.kernel_fft.is_some() { self.prepare(b, self.fft_dims); }
     }
 
-    pub fn run(&mut self, channel: &Channel) {
-        // apply padding
-        let p = Dim4::new(&[self.radius as u64, self.radius as u64, 0, 0]);
-        let dims = channel.matrix.dims().get().to_vec();
-        let temp = pad(&channel.matrix, p, p, BorderType::PERIODIC);
+    // periodic worlds are transformed as they are, other boundaries need
+    // padding so that circular convolution doesn't wrap around
+    pub fn padding(&self, dims: [usize; 3]) -> usize {
+        let h = self.radius * 2 + 1;
+        let fits = (0..self.dimensions).all(|d| dims[d] >= h );
+        if self.boundary == Boundary::Periodic && fits { 0 } else { self.radius }
+    }
+    // size of transformed world
+    fn padded(&self, dims: [usize; 3]) -> [usize; 3] {
+        let p = self.padding(dims);
+        dims.map(|n| if n > 1 { n + 2 * p } else { n } )
+    }
+
+    // layers with equal key can share single transform of their source
+    pub fn spectrum_key(&self, source: usize, dims: [usize; 3]) -> (usize, Boundary, usize, usize) {
+        (source, self.boundary, self.padding(dims), self.dimensions)
+    }
+
+    // channels read by layer, with their weights
+    pub fn inputs(&self) -> Vec<(usize, f32)> {
+        if self.sources.is_empty() { return vec![(self.source_key, 1.)] }
+        self.sources.iter().map(|(k,w)| (*k, *w) ).sorted_by_key(|(k,_)| *k ).collect()
+    }
+
+    pub fn transform(&self, b: &B, matrix: &B::Matrix) -> B::Spectrum {
+        let p = self.padding(b.dims(matrix));
+        if p == 0 { return b.transform(matrix, self.dimensions) }
+        b.transform(&b.pad(matrix, p, self.boundary), self.dimensions)
+    }
+
+    // kernel spectrum for world of given size, kept until size or kernel changes
+    pub fn prepare(&mut self, b: &B, dims: [usize; 3]) {
+        self.kernel_fft = Some(b.prepare(&self.kernel_lookup, self.padded(dims), self.dimensions));
+        self.fft_dims = dims;
+    }
+    pub fn is_prepared(&self, dims: [usize; 3]) -> bool {
+        self.kernel_fft.is_some() && self.fft_dims == dims
+    }
 
-        self.matrix_out = convolve2(&temp, &self.kernel_lookup, ConvMode::DEFAULT, ConvDomain::FREQUENCY);
+    // output for spectra of inputs with their weights, kernel has to be prepared for dims
+    pub fn run(&self, b: &B, spectra: &[(&B::Spectrum, f32)], dims: [usize; 3]) -> B::Matrix {
+        let kernel = self.kernel_fft.as_ref().expect("layer kernel isn't prepared");
+        match self.mix {
+            Mix::Input => {
+                // transform is linear, so sources can be mixed in frequency domain
+                let t = self.convolve(b, &b.combine(spectra), kernel, dims);
+                b.function_mapped(&self.growth_map, &t, &self.parameter_maps)
+            },
+            Mix::Output => spectra.iter().map(|(s,w)|{
+                let t = self.convolve(b, s, kernel, dims);
+                b.scale(&b.function_mapped(&self.growth_map, &t, &self.parameter_maps), *w)
+            }).reduce(|x,y| b.add(&x, &y) ).unwrap(),
+        }
+    }
 
-        //remove padding
-        let seqs = [
-            Seq::new(self.radius as u32, dims[0] as u32 + self.radius as u32 - 1, 1),
-            Seq::new(self.radius as u32, dims[1] as u32 + self.radius as u32 - 1, 1),
-        ];
-        self.matrix_out = index(&self.matrix_out, &seqs).copy();
-        
-        self.matrix_out = self.growth_map.calc_array(&self.matrix_out);
+    // spectral multiply and inverse transform, padding is removed
+    pub fn convolve(&self, b: &B, spectrum: &B::Spectrum, kernel_fft: &B::Spectrum, dims: [usize; 3]) -> B::Matrix {
+        let p = self.padding(dims);
+        let out = b.inverse(spectrum, kernel_fft, self.padded(dims), self.dimensions);
+        if p == 0 { out } else { b.crop(&out, p) }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Channel {
-    pub matrix: Array<f32>,
-    pub matrix_out: Array<f32>,
+#[cfg(feature = "arrayfire")]
+impl Layer<ArrayFire> {
+    // map of growth parameter, .bin is used as it is, images are
+    // rescaled to world size and their brightness spread over low..high
+    pub fn load_parameter_map(&mut self, index: usize, path: &str, dims: [usize; 3], low: f32, high: f32) -> Result<(), PresetError> {
+        let map = if path.ends_with(".bin") { read_bin::<Array<f32>>(path)? } else {
+            // arrayfire would abort on a file it can't open
+            fs::metadata(path).map_err(|e| PresetError::new(path, Cause::Io(e)) )?;
+            if !is_imageio_available() { return Err(PresetError::new(path, Cause::Unsupported("image loading".to_string()))) }
+            let img = load_image::<f32>(path.to_string(), false);
+            let img = resize(&img, dims[0] as i64, dims[1] as i64, InterpType::BILINEAR);
+            img / 255_f32 * (high - low) + low
+        };
+        self.parameter_maps.insert(index, map);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Channel<B: Backend> {
+    pub matrix: B::Matrix,
+    pub matrix_out: B::Matrix, // rate of change in the last step
     pub weights: HashMap<usize, f32>, // layer key, weight 
+    pub walls: Option<B::Matrix>, // 1 - wall, 0 - free
 }
-impl Channel {
-    pub fn new(matrix: Array<f32>) -> Self {
+impl<B: Backend> Channel<B> {
+    pub fn new(matrix: B::Matrix) -> Self {
+        let b = B::default();
         Self { 
-            matrix_out: Array::<f32>::new_empty(matrix.dims()),
+            matrix_out: b.constant(0., b.dims(&matrix)),
             matrix, 
             weights: HashMap::new(),
+            walls: None,
         }
     }
 
-    // things to do after layer computation
-    pub fn finish(&mut self, delta: f32) {
-        //self.matrix_out = div(&self.matrix_out, &(self.layer_counter as f32), false );  // change is divided by amount of layers
-        self.matrix_out = mul( &self.matrix_out, &delta, false);   // incorporate delta
-        self.matrix = add(&self.matrix, &self.matrix_out, false);
-        self.matrix = clamp(&self.matrix, &0_f32, &1_f32, false);
+    // walls keep fixed value no matter what growth says
+    pub fn pin(&mut self, mask: &B::Matrix, value: f32) {
+        let b = B::default();
+        let free = b.offset(&b.scale(mask, -1.), 1.);
+        self.matrix = b.add(&b.mul(&self.matrix, &free), &b.scale(mask, value));
     }
 }
 
 
 
 
-impl PartialEq for Layer {
+impl<B: Backend> PartialEq for Layer<B> {
     fn eq(&self, other: &Self) -> bool {
         self.kernel == other.kernel &&
         self.growth_map == other.growth_map &&
         self.source_key == other.source_key &&
-        self.radius == other.radius
+        self.sources == other.sources &&
+        self.mix == other.mix &&
+        self.radius == other.radius &&
+        self.boundary == other.boundary &&
+        self.dimensions == other.dimensions &&
+        self.anisotropy == other.anisotropy
     }
 }
-impl fmt::Debug for Layer {
+impl<B: Backend> fmt::Debug for Layer<B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut l = self.source_key.to_string();
+        let mut l = format!("{:?}", self.inputs());
+        if self.sources.len() > 1 { l += &format!(" {}", self.mix); }
         l += "\tK: ";
         l += &format!("{:?}", self.kernel);
         l += "G: ";
         l += &format!("{:?}", self.growth_map);
         l += "R: ";
         l += &self.radius.to_string();
+        if !self.parameter_maps.is_empty() {
+            l += " M: ";
+            l += &format!("{:?}", self.parameter_maps.keys().sorted().collect::<Vec<_>>());
+        }
+        l += " B: ";
+        l += &self.boundary.to_string();
+        l += " D: ";
+        l += &self.dimensions.to_string();
+        if !self.anisotropy.is_isotropic() {
+            l += " A: ";
+            l += &format!("{:?}", self.anisotropy);
+        }
         write!(f, "{}", l)
     }
 }
 
-impl PartialEq for Channel {
+impl<B: Backend> PartialEq for Channel<B> {
     fn eq(&self, other: &Self) -> bool {
-        self.matrix.dims() == other.matrix.dims()
+        let b = B::default();
+        b.dims(&self.matrix) == b.dims(&other.matrix)
     }
 }
-impl fmt::Debug for Channel {
+impl<B: Backend> fmt::Debug for Channel<B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut l = "".to_string();
-        l += &format!("{:?}", self.matrix.dims());
+        l += &format!("{:?}", B::default().dims(&self.matrix));
         write!(f, "{}", l)
     }
 }
@@ -1,106 +1,290 @@
 #![feature(duration_millis_float)]
-use std::{collections::HashMap, fs, io::{ErrorKind, Read, Write}};
-use std::time::Instant;
+use std::{collections::HashMap, env, fs, io::{ErrorKind, Read, Write}, process::ExitCode, thread};
+use std::time::{Duration, Instant};
 use std::net::{TcpListener, TcpStream};
 
-use lenia_gpu::{FrameTimeAnalyzer, Function, PackageLenia, Shape};
-use lenia_gpu::{Channel, Layer, Lenia, DataLenia};
+use lenia_gpu::{FrameTimeAnalyzer, Function, PackageLenia, Presets, Shape};
+use lenia_gpu::{ArrayFire, Channel, Layer, Lenia, DataLenia, Event, Tracker};
 
 use arrayfire::*;
+use itertools::Itertools;
 
-fn _creator(size: (usize, usize)) -> (Lenia, u8) {
-    let sx = 32;
-    let sy = 16;
-    let mut matrix = randn::<f32>(Dim4::new(&[size.0 as u64 / sy,size.1 as u64 / sx,1,1]));
+// new random world with the first free key, protocol carries keys as u8;
+// depth above 1 makes a volume with spherical kernel
+fn _creator(size: (usize, usize, usize), presets: &Presets) -> Result<(Lenia<ArrayFire>, u8), String> {
+    let key = u8::try_from(presets.free_key()).map_err(|_| "Can't create world: every preset key up to 255 is taken".to_string() )?;
+    let volume = size.2 > 1;
+    let (sx, sy) = if volume { (8, 8) } else { (32, 16) };
+    let mut matrix = randn::<f32>(Dim4::new(&[size.0 as u64 / sy, size.1 as u64 / sx, (size.2 as u64 / sx).max(1), 1]));
     matrix = &matrix - 0.8_f32;
     matrix = clamp(&matrix, &0_f32, &1_f32, false);
     matrix = scale(&matrix, sy as f32, sx as f32, size.0 as i64, size.1 as i64, InterpType::NEAREST);
+    if volume {
+        // scale works in x-y plane only, depth is stretched with axes turned
+        matrix = reorder_v2(&matrix, 2, 0, Some(vec![1]));
+        matrix = scale(&matrix, sx as f32, 1., size.2 as i64, size.0 as i64, InterpType::NEAREST);
+        matrix = reorder_v2(&matrix, 1, 2, Some(vec![0]));
+    }
 
     let mut lenia = Lenia::new(0.1, HashMap::new(), HashMap::new());
     
     let channel = Channel::new(matrix);
     lenia.channels.insert(0, channel);
 
-    let layer = Layer::new(
+    let mut layer = Layer::new(
         Function::new(Shape::GaussianBump, false, vec![0.15, 0.5], true), 
         Function::new(Shape::GaussianBump, true, vec![0.015, 0.15], true), 
-        0, 92
+        0, if volume { 12 } else { 92 }
     );
+    layer.dimensions = if volume { 3 } else { 2 };
     lenia.layers.insert( 0, layer);
 
     lenia.channels.get_mut(&0).unwrap().weights.insert(0, 1.);
     
-    let dirs = fs::read_dir("data/").unwrap().filter(|e| e.is_ok() ).filter(|e| e.as_ref().unwrap().path().is_dir() )
-        .map(|e| e.unwrap().path().file_name().unwrap().to_str().unwrap().to_owned().trim().parse::<u8>() ).filter(|e| e.is_ok() )
-        .map(|e| e.unwrap() ).collect::<Vec<u8>>();
+    Ok((lenia, key))
+}
 
-    let id = (u8::min_value()..=u8::max_value()).into_iter().filter(|v| !dirs.contains(v) ).min().unwrap();
+// --headless runs without window, --snapshots <dir> saves png every --every <n> steps,
+// --presets <dir> overrides LENIA_PRESETS
+struct Options {
+    headless: bool,
+    presets: Presets,
+    snapshots: Option<String>,
+    every: usize,
+}
+fn options() -> Result<Options, String> {
+    let args = env::args().collect::<Vec<_>>();
+    let value = |flag: &str| args.iter().position(|a| a == flag ).and_then(|i| args.get(i + 1) ).cloned();
+    let every = match value("--every") {
+        Some(e) => e.parse().map_err(|_| format!("--every needs a number of steps, got {}", e) )?,
+        None => 100,
+    };
+    Ok(Options {
+        headless: args.iter().any(|a| a == "--headless"),
+        presets: value("--presets").map_or_else(Presets::from_env, |r| Presets::new(&r) ),
+        snapshots: value("--snapshots"),
+        every,
+    })
+}
 
-    (lenia, id)
+// loaded preset that is ready to run, or why it isn't
+fn load(key: u8, presets: &Presets) -> Result<Lenia<ArrayFire>, String> {
+    DataLenia::load(&presets.path(key as usize)).map_err(|e| format!("Can't load preset {}: {}", key, e) )
+}
+
+// creatures of one channel, followed every step once client asks for them,
+// events pile up until they are sent
+struct Tracking {
+    channel: usize,
+    tracker: Tracker,
+    events: Vec<Event>,
+}
+impl Tracking {
+    fn update(&mut self, lenia: &Lenia<ArrayFire>) {
+        if let Some(ch) = lenia.channels.get(&self.channel) {
+            self.events.extend_from_slice(self.tracker.update(&ch.matrix));
+        }
+    }
 }
 
-fn main() {
+fn failure(reason: String) -> Vec<u8> {
+    eprintln!("{}", reason);
+    let mut p = reason.into_bytes();
+    p.insert(0, 0);
+    p
+}
+
+fn main() -> ExitCode {
+    let options = match options() {
+        Ok(o) => o,
+        Err(e) => { eprintln!("{}", e); return ExitCode::FAILURE }
+    };
     get_available_backends();
     set_device(0);
     info();
+    // snapshots are checked before anything runs, not at the first save
+    if let Some(dir) = &options.snapshots {
+        if !is_imageio_available() { eprintln!("Can't save snapshots, arrayfire is built without image support"); return ExitCode::FAILURE }
+        if let Err(e) = fs::create_dir_all(dir) { eprintln!("Can't create {}: {}", dir, e); return ExitCode::FAILURE }
+    }
     
     let window_size: (usize, usize) = (1024, 1024 );
 
-    let mut current_lenia = 0;
-    let mut lenia = DataLenia::load(current_lenia as usize);
+    // broken preset doesn't stop the server, new world is started instead
+    let (mut lenia, mut current_lenia) = match load(0, &options.presets) {
+        Ok(l) => (l, 0),
+        Err(e) => {
+            eprintln!("{}, starting new world", e);
+            match _creator((2048, 2048, 1), &options.presets) {
+                Ok(created) => created,
+                Err(e) => { eprintln!("{}", e); return ExitCode::FAILURE }
+            }
+        },
+    };
     lenia.init();
 
-    let win = Window::new(window_size.0 as i32, window_size.1 as i32, "LeniaCore".to_string());
+    let win = if options.headless { None } else {
+        Some(Window::new(window_size.0 as i32, window_size.1 as i32, "LeniaCore".to_string()))
+    };
     let listener = TcpListener::bind("127.0.0.1:2137").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let mut client: Option<TcpStream> = None;
     let mut buffer = [0; 1024];
     let mut pause = false;
+    let mut running = true;
     let mut fta = FrameTimeAnalyzer::new(10);
+    let mut tracking: Option<Tracking> = None;
     let mut now;
 
-    for c in listener.incoming() {
-        let c: TcpStream = c.unwrap();
-        c.set_nonblocking(true).unwrap();
-        
-        while !win.is_closed() {
-            now = Instant::now();
-            win.draw_image(&lenia.img, None);
-            if !pause {lenia.evaluate()}
-            lenia.generate_image();
-            handle_client(&c, &mut buffer, &mut lenia, &mut pause, &mut fta, &mut current_lenia);
-            fta.add_frame_time(now.elapsed().as_millis_f32());
+    // simulation runs also before anyone connects
+    while running && !win.as_ref().is_some_and(|w| w.is_closed() ) {
+        now = Instant::now();
+        if client.is_none() {
+            if let Ok((c, _)) = listener.accept() {
+                c.set_nonblocking(true).unwrap();
+                client = Some(c);
+            }
+        }
+        if let Some(w) = &win { w.draw_image(&lenia.img, None); }
+        if !pause {
+            lenia.evaluate();
+            if let Some(t) = &mut tracking { t.update(&lenia); }
+            if let Some(dir) = &options.snapshots {
+                let step = lenia.step as usize;
+                if step.is_multiple_of(options.every.max(1)) {
+                    lenia.save_image(&format!("{}/{}_{}.png", dir, current_lenia, step), None);
+                }
+            }
         }
-        return
-    } 
+        else if win.is_none() { thread::sleep(Duration::from_millis(10)); }
+        if win.is_some() { lenia.generate_image(); }
+        // closed or broken connection frees the place for the next client
+        if let Some(c) = &client {
+            if !handle_client(c, &mut buffer, &mut lenia, &mut pause, &mut running, &mut fta, &mut tracking, &mut current_lenia, &options.presets) {
+                client = None;
+            }
+        }
+        fta.add_frame_time(now.elapsed().as_millis_f32());
+    }
+    ExitCode::SUCCESS
 }
 
 
+// false when client is gone
+#[allow(clippy::too_many_arguments)]
 fn handle_client(
     mut stream: &TcpStream, buffer: &mut[u8],
-    lenia: &mut Lenia, pause: &mut bool,
-    fta: &mut FrameTimeAnalyzer,
-    lid: &mut u8
-) {
+    lenia: &mut Lenia<ArrayFire>, pause: &mut bool, running: &mut bool,
+    fta: &mut FrameTimeAnalyzer, tracking: &mut Option<Tracking>,
+    lid: &mut u8, presets: &Presets
+) -> bool {
     match stream.read(buffer) {
-        Ok(0) => {}
+        Ok(0) => false,
         Ok(bytes_read) => {
             let response = match buffer[0] {
                 10 => {
                     *pause = !*pause;
                     vec![8]
                 }
+                // failures are answered with 0 and the reason
                 11 => {
-                    DataLenia::save(*lid as usize, lenia);
-                    vec![1]
+                    match DataLenia::save(&presets.path(*lid as usize), lenia) {
+                        Ok(_) => vec![1],
+                        Err(e) => failure(format!("Can't save preset {}: {}", lid, e)),
+                    }
                 }
+                12 if bytes_read < 2 => failure("Can't load preset: key is missing".to_string()),
                 12 => {
-                    *lenia = DataLenia::load(buffer[1] as usize);
-                    *lid = buffer[1];
-                    vec![1]
+                    // only numbered presets of the root, keys above 255 and other directories
+                    // can't be loaded over tcp (search and sweep take any path)
+                    match load(buffer[1], presets) {
+                        Ok(loaded) => {
+                            *lenia = loaded;
+                            lenia.init();
+                            *lid = buffer[1];
+                            *tracking = None;
+                            vec![1]
+                        }
+                        Err(e) => failure(e),
+                    }
                 }
+                13 if bytes_read >= 2 && ![2, 3].contains(&buffer[1]) => failure(format!("Can't create world with {} dimensions", buffer[1])),
                 13 => {
-                    (*lenia, *lid) = _creator((2048, 2048));
+                    // optionally number of dimensions, 3 gives a volume
+                    let size = if bytes_read >= 2 && buffer[1] == 3 { (128, 128, 128) } else { (2048, 2048, 1) };
+                    match _creator(size, presets) {
+                        Ok(created) => {
+                            (*lenia, *lid) = created;
+                            lenia.init();
+                            *tracking = None;
+                            vec![1]
+                        }
+                        Err(e) => failure(e),
+                    }
+                }
+                14 => {
+                    lenia.slice += 1;
+                    vec![1]
+                }
+                15 => {
+                    lenia.slice = lenia.slice.saturating_sub(1);
                     vec![1]
                 }
+                16 => {
+                    // factor and optionally new delta, both as f32
+                    let float = |i: usize| f32::from_le_bytes(buffer[i..i + 4].try_into().unwrap());
+                    let factor = if bytes_read >= 5 { Some(float(1)) } else { None };
+                    let delta = if bytes_read >= 9 { Some(float(5)) } else { None };
+                    match factor {
+                        None => failure("Can't rescale: factor is missing".to_string()),
+                        Some(f) if !f.is_finite() || f <= 0. => failure(format!("Can't rescale by {}", f)),
+                        _ if delta.is_some_and(|d| !d.is_finite() || d <= 0. ) => failure(format!("Can't rescale with delta {}", delta.unwrap())),
+                        Some(f) => {
+                            lenia.rescale(f, delta);
+                            vec![1]
+                        }
+                    }
+                }
+                17 => {
+                    bincode::serialize(&lenia.analytics).unwrap()
+                }
+                18 => {
+                    // width, height as u32 and rgb rows
+                    lenia.generate_image();
+                    let img = clamp(&lenia.img, &0_f32, &1_f32, false) * 255_f32;
+                    let img = reorder_v2(&img.cast::<u8>(), 2, 1, Some(vec![0]));
+                    let d = img.dims();
+                    let mut frame = vec![0_u8; img.elements()];
+                    img.host(&mut frame);
+                    let mut p = (d[1] as u32).to_le_bytes().to_vec();
+                    p.extend((d[2] as u32).to_le_bytes());
+                    p.extend(frame);
+                    p
+                }
+                19 => {
+                    *running = false;
+                    vec![1]
+                }
+                20 if bytes_read < 2 => failure("Can't track: channel is missing".to_string()),
+                20 => {
+                    // channel and optionally threshold as f32, other channel or threshold starts over
+                    let channel = buffer[1] as usize;
+                    let threshold = if bytes_read >= 6 { f32::from_le_bytes(buffer[2..6].try_into().unwrap()) } else { 0.1 };
+                    if !lenia.channels.contains_key(&channel) { failure(format!("Can't track channel {}: it doesn't exist", channel)) }
+                    else if !threshold.is_finite() { failure(format!("Can't track with threshold {}", threshold)) }
+                    else {
+                        if !tracking.as_ref().is_some_and(|t| t.channel == channel && t.tracker.threshold == threshold ) {
+                            let mut t = Tracking { channel, tracker: Tracker::new(threshold), events: vec![] };
+                            t.update(lenia);
+                            *tracking = Some(t);
+                        }
+                        let t = tracking.as_mut().unwrap();
+                        let mut p = bincode::serialize(&(&t.tracker.creatures, &t.events)).unwrap();
+                        t.events.clear();
+                        p.insert(0, 1);
+                        p
+                    }
+                }
                 9 => {
                     vec![*fta.smooth_frame_time() as u8 ]
                 }
@@ -110,19 +294,26 @@ fn handle_client(
                     p
                 }
                 8 => {
-                    let p:PackageLenia = bincode::deserialize(&buffer[1..bytes_read]).unwrap();
-                    PackageLenia::update_lenia(&p, lenia);
-                    vec![1]
+                    match bincode::deserialize::<PackageLenia>(&buffer[1..bytes_read]) {
+                        Ok(p) => match PackageLenia::update_lenia(&p, lenia) {
+                            Ok(_) => vec![1],
+                            Err(problems) => failure(format!("Update refused: {}", problems.iter().join(", "))),
+                        },
+                        Err(e) => failure(format!("Malformed update: {}", e)),
+                    }
                 }
                 _ => {vec![0]}
             };
 
-            match stream.write_all(&response) {
-                Ok(_) => {}
-                Err(e) => eprintln!("Error sending response: {}", e),
+            // frames don't fit into socket buffer, so response is written blocking
+            let sent = stream.set_nonblocking(false).and_then(|_| stream.write_all(&response) )
+                .and_then(|_| stream.set_nonblocking(true) );
+            match sent {
+                Ok(_) => true,
+                Err(e) => { eprintln!("Error sending response: {}", e); false }
             }
         }
-        Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
-        Err(e) => eprintln!("Error reading from socket: {}", e),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(e) => { eprintln!("Error reading from socket: {}", e); false }
     }
 }
@@ -0,0 +1,90 @@
+use std::{env, process::ExitCode, str::FromStr};
+
+use lenia_gpu::{ArrayFire, DataLenia, Fitness, Presets, Search};
+
+use arrayfire::*;
+
+const USAGE: &str = "search [--presets data] [--preset 0|<dir>] [--population 16] [--generations 10] [--steps 200] \
+[--fitness mean|life|speed] [--rate 0.1] [--survivors 4] [--seed 0] [--out <free keys>]";
+
+struct Options {
+    presets: Presets,
+    preset: String,
+    population: usize,
+    generations: usize,
+    steps: usize,
+    fitness: Fitness,
+    rate: Option<f32>,
+    survivors: Option<usize>,
+    seed: u64,
+    out: Option<usize>,
+}
+
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("--{} needs a number, got {}", flag, value) )
+}
+
+// --name value pairs, anything else is an error
+fn options() -> Result<Options, String> {
+    let mut o = Options {
+        presets: Presets::from_env(), preset: "0".to_string(),
+        population: 16, generations: 10, steps: 200, fitness: Fitness::Mean,
+        rate: None, survivors: None, seed: 0, out: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(a) = args.next() {
+        let flag = a.strip_prefix("--").ok_or(format!("unexpected argument {}", a))?;
+        let value = args.next().ok_or(format!("--{} needs a value", flag))?;
+        match flag {
+            "presets" => o.presets = Presets::new(&value),
+            "preset" => o.preset = value,
+            "population" => o.population = number(flag, &value)?,
+            "generations" => o.generations = number(flag, &value)?,
+            "steps" => o.steps = number(flag, &value)?,
+            "fitness" => o.fitness = Fitness::from_name(&value).ok_or(format!("unknown fitness {}", value))?,
+            "rate" => o.rate = Some(number(flag, &value)?),
+            "survivors" => o.survivors = Some(number(flag, &value)?),
+            "seed" => o.seed = number(flag, &value)?,
+            "out" => o.out = Some(number(flag, &value)?),
+            _ => return Err(format!("unknown option --{}", flag)),
+        }
+    }
+    Ok(o)
+}
+
+fn main() -> ExitCode {
+    let o = match options() {
+        Ok(o) => o,
+        Err(e) => { eprintln!("{}\n{}", e, USAGE); return ExitCode::FAILURE }
+    };
+
+    set_device(0);
+    // --presets overrides LENIA_PRESETS, --preset is a key in it or any directory
+    let preset = o.presets.resolve(&o.preset);
+    let origin = match DataLenia::load::<ArrayFire>(&preset) {
+        Ok(l) => l,
+        Err(e) => { eprintln!("Can't load preset: {}", e); return ExitCode::FAILURE }
+    };
+
+    let fitness = o.fitness;
+    let mut search = Search::new(origin, o.population, fitness, o.steps, o.seed);
+    if let Some(r) = o.rate { search.rate = r; }
+    if let Some(s) = o.survivors { search.survivors = s; }
+
+    (0..o.generations).for_each(|g|{
+        if g > 0 { search.breed(); }
+        search.evaluate();
+        println!("Generation {}: best {} = {:.5}", g, fitness, search.scores[0]);
+    });
+
+    // without --out every survivor takes first free key, existing presets are never overwritten
+    let mut failed = false;
+    search.best().enumerate().for_each(|(i,(genome,score))|{
+        let key = o.out.map_or_else(|| o.presets.free_key(), |k| k + i);
+        match DataLenia::save(&o.presets.path(key), genome) {
+            Ok(_) => println!("Saved preset {} ({} = {:.5})", key, fitness, score),
+            Err(e) => { eprintln!("Can't save preset {}: {}", key, e); failed = true; }
+        }
+    });
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
@@ -0,0 +1,93 @@
+use std::{env, fs::{self, File}, io::Write, process::ExitCode};
+
+use lenia_gpu::{Axis, DataLenia, Outcome, Presets, Sweep};
+
+use arrayfire::*;
+
+const USAGE: &str = "sweep [--presets data] [--preset 0|<dir>] [--steps 500] [--out sweep.csv] [--thumbnails <dir>] [--size 128] \
+<target>=<from>..<to>:<count> | <target>=<v0>,<v1>,... ...\n\
+targets: delta, <layer>.kernel.<i>, <layer>.growth.<i>, <layer>.radius, <channel>.weight.<layer>";
+
+struct Options {
+    presets: Presets,
+    preset: String,
+    steps: usize,
+    out: String,
+    thumbnails: Option<String>,
+    size: u64,
+    axes: Vec<Axis>,
+}
+
+fn options() -> Result<Options, String> {
+    let mut o = Options { presets: Presets::from_env(), preset: "0".to_string(), steps: 500, out: "sweep.csv".to_string(), thumbnails: None, size: 128, axes: vec![] };
+    let mut args = env::args().skip(1);
+    while let Some(a) = args.next() {
+        let Some(flag) = a.strip_prefix("--") else {
+            o.axes.push(Axis::parse(&a)?);
+            continue
+        };
+        let value = args.next().ok_or(format!("--{} needs a value", flag))?;
+        let number = || value.parse::<usize>().map_err(|_| format!("--{} needs a number", flag) );
+        match flag {
+            "presets" => o.presets = Presets::new(&value),
+            "preset" => o.preset = value,
+            "steps" => o.steps = number()?,
+            "out" => o.out = value,
+            "thumbnails" => o.thumbnails = Some(value),
+            "size" => o.size = number()? as u64,
+            _ => return Err(format!("unknown option --{}", flag)),
+        }
+    }
+    Ok(o)
+}
+
+fn main() -> ExitCode {
+    let o = match options() {
+        Ok(o) => o,
+        Err(e) => { eprintln!("{}\n{}", e, USAGE); return ExitCode::FAILURE }
+    };
+    match sweep(o) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => { eprintln!("{}", e); ExitCode::FAILURE }
+    }
+}
+
+// every point of the grid, invalid points are written as such and don't stop the sweep
+fn sweep(o: Options) -> Result<(), String> {
+    set_device(0);
+    // --presets overrides LENIA_PRESETS, --preset is a key in it or any directory
+    let base = DataLenia::load(&o.presets.resolve(&o.preset)).map_err(|e| format!("Can't load preset: {}", e) )?;
+    let sweep = Sweep { base, axes: o.axes, steps: o.steps };
+    if let Some(dir) = &o.thumbnails {
+        fs::create_dir_all(dir).map_err(|e| format!("Can't create {}: {}", dir, e) )?;
+    }
+
+    let mut csv = File::create(&o.out).map_err(|e| format!("Can't create {}: {}", o.out, e) )?;
+    let mut write = |line: String| writeln!(csv, "{}", line).map_err(|e| format!("Can't write {}: {}", o.out, e) );
+    let mut header = sweep.axes.iter().map(|a| a.target.to_string() ).collect::<Vec<_>>();
+    header.extend(["fitness", "mass", "outcome", "thumbnail"].map(String::from));
+    write(header.join(","))?;
+
+    let points = sweep.points();
+    points.iter().enumerate().try_for_each(|(i,point)|{
+        let mut row = point.iter().map(|v| v.to_string() ).collect::<Vec<_>>();
+        match sweep.run(point) {
+            Ok(mut world) => {
+                let mass = world.analytics.channels.values().map(|s| s.mass ).sum::<f32>();
+                let thumbnail = o.thumbnails.as_ref().map_or(String::new(), |dir|{
+                    let path = format!("{}/{}.png", dir, i);
+                    world.save_image(&path, Some(o.size));
+                    path
+                });
+                row.extend([world.fitness.to_string(), mass.to_string(), Outcome::of(&world).to_string(), thumbnail]);
+            },
+            Err(e) => {
+                eprintln!("Point {:?} skipped: {}", point, e);
+                row.extend(["", "", "invalid", ""].map(String::from));
+            },
+        }
+        write(row.join(","))?;
+        println!("{}/{}", i + 1, points.len());
+        Ok(())
+    })
+}
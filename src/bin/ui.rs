@@ -1,6 +1,6 @@
-use lenia_gpu::{Cycle, Function, PackageLenia, Shape};
+use lenia_gpu::{Cycle, Function, PackageLenia, Presets, Shape};
 use eframe::egui::{self, Color32, Frame, Key, Pos2, RichText, Stroke, Ui, UiBuilder, Vec2};
-use std::fs;
+use std::env;
 use std::path::Path;
 use std::process::{Command, Child};
 use std::net::TcpStream;
@@ -9,6 +9,7 @@ use std::time::Instant;
 
 struct Handler {
     arrayfire: Child,
+    presets: Presets,
     stream: TcpStream,
     buffer: [u8;1024],
     timers: Vec<Instant>,
@@ -17,6 +18,7 @@ struct Handler {
     load_lenia: (bool, u8),
     lenia: PackageLenia,
     lenia_number: u8,
+    status: String, // last failure reported by compute
     delta: usize,
     kernel_shape: [f32;200],
     growth_shape: [f32;200],
@@ -60,6 +62,26 @@ pub fn draw_lines_in_box(ui: &mut Ui, values: &[f32], desired_width: f32, desire
     );
 }
 
+// label of parameter, None for parameters that shape doesn't use
+fn parameter_name(shape: &Shape, i: usize) -> Option<String> {
+    match shape {
+        Shape::GaussianBumpMulti => match i % 3 {
+            0 => Some(format!("{} Width", i/3)),
+            1 => Some(format!("{} Offset", i/3)),
+            _ => Some(format!("{} Strength", i/3)),
+        },
+        Shape::Rings(_) => Some(format!("{} Ring", i)),
+        Shape::Table if i.is_multiple_of(2) => Some(format!("{} X", i/2)),
+        Shape::Table => Some(format!("{} Y", i/2)),
+        Shape::Expression(e) => e.names.get(i).cloned(),
+        _ => match i {
+            0 => Some("0 Width".to_string()),
+            1 => Some("0 Offset".to_string()),
+            _ => None,
+        },
+    }
+}
+
 struct Arrow {
     user: usize,
     runner: usize
@@ -81,7 +103,7 @@ impl Arrow {
         self.runner += 1;
         if self.user != self.runner - 1 { return }
         ui.label(RichText::new("^^^^^^^^").color(Color32::RED));
-        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) { *x -= 1 }
+        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) { *x = x.saturating_sub(1) }
         else if ctx.input(|i| i.key_pressed(Key::ArrowRight)) { *x += 1 }
     }
     fn cursor_bool(&mut self, ui: &mut Ui, ctx: &eframe::egui::Context, x: &mut bool) {
@@ -95,12 +117,31 @@ impl Arrow {
         self.runner += 1;
         if self.user != self.runner - 1 {return}
         ui.label(RichText::new("^^^^^^^^").color(Color32::RED));
-        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) {x.shape.previous()}
-        else if ctx.input(|i| i.key_pressed(Key::ArrowRight)) {x.shape.next()}
+        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) {x.previous()}
+        else if ctx.input(|i| i.key_pressed(Key::ArrowRight)) {x.next()}
 
         if x.shape == Shape::GaussianBumpMulti {
             while x.parameters.len() % 3 != 0 {x.parameters.push(1.);}
         }
+        else if x.shape == Shape::Table {
+            while x.parameters.len() < 4 || !x.parameters.len().is_multiple_of(2) {
+                let last = x.parameters.len() / 2;
+                x.parameters.push(last as f32);
+            }
+        }
+        else if let Shape::Expression(e) = &x.shape {
+            while x.parameters.len() < e.names.len() {x.parameters.push(1.);}
+        }
+        else if !matches!(x.shape, Shape::Rings(_)) {
+            while x.parameters.len() < 2 {x.parameters.push(1.);}
+        }
+    }
+    fn cursor_cycle<T: Cycle>(&mut self, ui: &mut Ui, ctx: &eframe::egui::Context, x: &mut T) {
+        self.runner += 1;
+        if self.user != self.runner - 1 {return}
+        ui.label(RichText::new("^^^^^^^^").color(Color32::RED));
+        if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) {x.previous()}
+        else if ctx.input(|i| i.key_pressed(Key::ArrowRight)) {x.next()}
     }
     fn cursor_lenia(&mut self, ui: &mut Ui, ctx: &eframe::egui::Context) -> bool {
         self.runner += 1;
@@ -131,10 +172,9 @@ impl eframe::App for Handler {
             ui.heading("<<<<<<>>>>>>");
 
             // TODO, remember what number have lenia at the moment
-            let dirs = fs::read_dir("data/").unwrap().filter(|e| e.is_ok() ).filter(|e| e.as_ref().unwrap().path().is_dir() )
-                .map(|e| e.unwrap().path().file_name().unwrap().to_str().unwrap().to_owned().trim().parse::<u8>() ).filter(|e| e.is_ok() )
-                .map(|e| e.unwrap() ).collect::<Vec<u8>>();
+            let dirs = self.presets.keys().into_iter().filter_map(|k| u8::try_from(k).ok() ).collect::<Vec<u8>>();
             ui.label(format!("Loaded: {}", self.lenia_number));
+            if !self.status.is_empty() { ui.label(RichText::new(&self.status).color(Color32::RED)); }
             ui.label(format!("Found presets:"));
             dirs.iter().for_each(|d| { 
                 ui.label(format!(" - {}", d));
@@ -142,6 +182,16 @@ impl eframe::App for Handler {
                 else {self.load_lenia = (self.arrow.cursor_lenia(ui, ctx), *d)}
             });
 
+            ui.heading("<<<<<<>>>>>>");
+            ui.label(format!("Integrator: {}", self.lenia.lenia.integrator));
+            self.arrow.cursor_cycle(ui, ctx, &mut self.lenia.lenia.integrator);
+            ui.label(format!("Growth noise: {:>.4}", self.lenia.lenia.noise.growth));
+            self.arrow.cursor_f32(ui, ctx, &mut self.lenia.lenia.noise.growth);
+            ui.label(format!("Update skip: {:>.4}", self.lenia.lenia.noise.update));
+            self.arrow.cursor_f32(ui, ctx, &mut self.lenia.lenia.noise.update);
+            ui.label(format!("Dropout: {:>.4}", self.lenia.lenia.noise.dropout));
+            self.arrow.cursor_f32(ui, ctx, &mut self.lenia.lenia.noise.dropout);
+
             ui.heading("<<<<<<>>>>>>");
             ui.label(format!("Layer nr: {}", self.layer_nr));
             self.arrow.cursor_usize(ui, ctx, &mut self.layer_nr);
@@ -149,7 +199,30 @@ impl eframe::App for Handler {
             if self.lenia.layers.len() > self.layer_nr {
                 let layer = &mut self.lenia.layers[self.layer_nr];
                 ui.label( format!("Layer key: {}", layer.0) );
-                ui.label( format!("Layer source: {}", layer.1.source_key) );
+                if layer.1.sources.is_empty() {
+                    ui.label( format!("Layer source: {}", layer.1.source_key) );
+                } else {
+                    ui.label( format!("Sources mixed at: {}", layer.1.mix) );
+                    self.arrow.cursor_cycle(ui, ctx, &mut layer.1.mix);
+                    layer.1.sources.iter().zip(layer.1.source_weights.iter_mut()).for_each(|(k,w)|{
+                        ui.label( format!("Source: {} | Weight: {}", *k, *w) );
+                        self.arrow.cursor_f32(ui, ctx, w);
+                    });
+                }
+                ui.label( format!("Dimensions: {}", layer.1.dimensions) );
+                ui.label( format!("Boundary: {}", layer.1.boundary) );
+                self.arrow.cursor_cycle(ui, ctx, &mut layer.1.boundary );
+                let a = &mut layer.1.anisotropy;
+                ui.label( format!("Ellipse ratio: {:>.4}", a.ratio) );
+                self.arrow.cursor_f32(ui, ctx, &mut a.ratio );
+                ui.label( format!("Orientation: {:>.4}", a.angle) );
+                self.arrow.cursor_f32(ui, ctx, &mut a.angle );
+                ui.label( format!("Harmonic order: {}", a.order) );
+                self.arrow.cursor_usize(ui, ctx, &mut a.order );
+                ui.label( format!("Harmonic amplitude: {:>.4}", a.amplitude) );
+                self.arrow.cursor_f32(ui, ctx, &mut a.amplitude );
+                ui.label( format!("Twist: {:>.4}", a.twist) );
+                self.arrow.cursor_f32(ui, ctx, &mut a.twist );
                 ui.heading("------------");
                 ui.label( format!("Kernel") );
                 ui.label( format!("Shape: {}", layer.1.kernel.shape) );
@@ -161,17 +234,11 @@ impl eframe::App for Handler {
                 if layer.1.kernel.hard_clip {ui.label( format!("With hard-clip") );}
                 else                        {ui.label( format!("With sigmoid-clip") );}
                 self.arrow.cursor_bool(ui, ctx, &mut layer.1.kernel.hard_clip );
+                let shape = layer.1.kernel.shape.clone();
                 layer.1.kernel.parameters.iter_mut().enumerate().for_each(|(i,p)|{
-                    if i > 1 && layer.1.kernel.shape != Shape::GaussianBumpMulti { return }
-                    match i % 3 {
-                        0 => { ui.label(format!("{} Width: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p ); }
-                        1 => { ui.label(format!("{} Offset: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p ); }
-                        2 => { ui.label(format!("{} Strength: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p ); }
-                        _ => {}
-                    }
+                    let Some(name) = parameter_name(&shape, i) else { return };
+                    ui.label(format!("{}: {:>.4}", name, *p));
+                    self.arrow.cursor_f32(ui, ctx, p );
                 });
                 self.kernel_shape.iter_mut().enumerate().for_each(|(i,y)|{
                     let x = if i < 100 { 100. - i as f32 } else {(i+1) as f32 -100.};
@@ -190,17 +257,11 @@ impl eframe::App for Handler {
                 if layer.1.growth_map.hard_clip {ui.label( format!("With hard-clip") );}
                 else                            {ui.label( format!("With sigmoid-clip") );}
                 self.arrow.cursor_bool(ui, ctx, &mut layer.1.growth_map.hard_clip );
+                let shape = layer.1.growth_map.shape.clone();
                 layer.1.growth_map.parameters.iter_mut().enumerate().for_each(|(i,p)|{
-                    if i > 1 && layer.1.growth_map.shape != Shape::GaussianBumpMulti { return }
-                    match i % 3 {
-                        0 => { ui.label(format!("{} Width: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p );}
-                        1 => { ui.label(format!("{} Offset: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p );}
-                        2 => { ui.label(format!("{} Strength: {:>.4}", i/3, *p));
-                            self.arrow.cursor_f32(ui, ctx, p );}
-                        _ => {}
-                    }
+                    let Some(name) = parameter_name(&shape, i) else { return };
+                    ui.label(format!("{}: {:>.4}", name, *p));
+                    self.arrow.cursor_f32(ui, ctx, p );
                 });
                 self.growth_shape.iter_mut().enumerate().for_each(|(x,y)| 
                     *y = layer.1.growth_map._calc((x+1) as f32 /200.) );
@@ -228,12 +289,17 @@ impl eframe::App for Handler {
 
 impl Default for Handler {
     fn default() -> Self {
-        let arrayfire = launch();
+        // --presets overrides LENIA_PRESETS, compute is started with the same root
+        let args = env::args().collect::<Vec<_>>();
+        let presets = args.iter().position(|a| a == "--presets" ).and_then(|i| args.get(i + 1) )
+            .map_or_else(Presets::from_env, |r| Presets::new(r) );
+        let arrayfire = launch(&presets);
         let mut stream = None;
         while stream.is_none() {stream = connect();}
 
         Self { 
             arrayfire: arrayfire.unwrap(), 
+            presets,
             stream: stream.unwrap(), 
             buffer: [0;1024], 
             timers: vec![Instant::now(); 2],
@@ -242,6 +308,7 @@ impl Default for Handler {
             load_lenia: (false, 0),
             lenia: PackageLenia::empty(),
             lenia_number: 0,
+            status: String::new(),
             delta: 0,
             kernel_shape: [0.;200],
             growth_shape: [0.;200],
@@ -259,10 +326,19 @@ impl Handler {
         let request = 
         if ctx.input(|i| i.key_pressed(Key::P)) {vec![10]}
         else if ctx.input(|i| i.key_pressed(Key::S)) {vec![11]}
+        else if ctx.input(|i| i.modifiers.shift && i.key_pressed(Key::N)) {vec![13, 3]}
         else if ctx.input(|i| i.key_pressed(Key::N)) {vec![13]}
+        else if ctx.input(|i| i.key_pressed(Key::PageUp)) {vec![14]}
+        else if ctx.input(|i| i.key_pressed(Key::PageDown)) {vec![15]}
+        else if ctx.input(|i| i.key_pressed(Key::Plus)) { [vec![16], 2_f32.to_le_bytes().to_vec()].concat() }
+        else if ctx.input(|i| i.key_pressed(Key::Minus)) { [vec![16], 0.5_f32.to_le_bytes().to_vec()].concat() }
         else {vec![]};
 
-        if !request.is_empty() { self.pull_lenia = true; self.send(&request); }
+        if !request.is_empty() {
+            self.pull_lenia = true;
+            let r = self.send(&request);
+            if [11, 13, 16].contains(&request[0]) { self.report(r); }
+        }
         
         if self.timers[0].elapsed().as_millis() > 250 { 
             let _ = self.send(&vec![9]);
@@ -278,17 +354,26 @@ impl Handler {
         if self.push_lenia {
             let mut p = bincode::serialize(&self.lenia).unwrap();
             p.insert(0, 8);
-            let _ = self.send(&p);
+            let r = self.send(&p);
+            self.report(r);
             self.push_lenia = false;
         }
         if self.load_lenia.0 {
-            self.send(&vec![12, self.load_lenia.1]);
+            let r = self.send(&vec![12, self.load_lenia.1]);
+            self.report(r);
             self.load_lenia.0 = false;
             self.pull_lenia = true;
         }
         true
     }
 
+    // update, save, load, create and rescale answer 1, or 0 followed by the reason
+    fn report(&mut self, bytes_read: usize) {
+        self.status = if bytes_read > 0 && self.buffer[0] == 0 {
+            String::from_utf8_lossy(&self.buffer[1..bytes_read]).to_string()
+        } else { String::new() };
+    }
+
     fn send(&mut self, request: &Vec<u8>) -> usize{
         match self.stream.write_all(request) {   
             Ok(_) => {
@@ -332,13 +417,13 @@ fn connect() -> Option<TcpStream> {
     }
 }
 
-fn launch() -> Option<Child> {
+fn launch(presets: &Presets) -> Option<Child> {
     let mut arrayfire: Option<Child> = None;
     
     let arrayfire_app_dir = Path::new("./");
 
     if let Ok(process) = Command::new("cargo")
-        .args(&["run", "-r", "--bin", "compute"])
+        .args(["run", "-r", "--bin", "compute", "--", "--presets", &presets.root])
         .current_dir(arrayfire_app_dir)
         .spawn() {
         arrayfire = Some(process);
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(feature = "arrayfire")]
+use arrayfire::*;
+
+
+
+// user written function shape, like "exp(-((x-m)/s)^2/2)",
+// names are bound to function parameters in order (m - 0, s - 1).
+// It's parsed once, when created or deserialized; formula that doesn't parse can't be
+// deserialized, so preset with a typo fails to load, errors of new() are kept for Function::check
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawFormula", into = "RawFormula")]
+pub struct Formula {
+    pub source: String,
+    pub names: Vec<String>,
+    pub parsed: Result<Expr, String>,
+}
+#[derive(Clone, Serialize, Deserialize)]
+struct RawFormula {
+    source: String,
+    #[serde(default)]
+    names: Vec<String>,
+}
+impl TryFrom<RawFormula> for Formula {
+    type Error = String;
+    fn try_from(raw: RawFormula) -> Result<Self, String> {
+        let f = Formula::new(&raw.source, raw.names);
+        match &f.parsed {
+            Ok(_) => Ok(f),
+            Err(reason) => Err(format!("expression \"{}\": {}", f.source, reason)),
+        }
+    }
+}
+impl From<Formula> for RawFormula {
+    fn from(f: Formula) -> Self { RawFormula { source: f.source, names: f.names } }
+}
+impl Formula {
+    pub fn new(source: &str, names: Vec<String>) -> Self {
+        let parsed = Expr::parse(source, &names);
+        Formula { source: source.to_string(), names, parsed }
+    }
+}
+impl PartialEq for Formula {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.names == other.names
+    }
+}
+impl Eq for Formula {}
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+
+// parsed formula of custom function shape, over x and named parameters
+#[derive(Clone, PartialEq)]
+pub enum Expr {
+    Num(f32),
+    X,
+    Param(usize), // index into function parameters
+    Neg(Box<Expr>),
+    Op(char, Box<Expr>, Box<Expr>), // + - * / ^
+    Call(Call, Box<Expr>),
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum Call { Exp, Log, Sqrt, Abs, Sin, Cos, Tanh, Floor }
+
+impl Call {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "exp" => Call::Exp,
+            "log" | "ln" => Call::Log,
+            "sqrt" => Call::Sqrt,
+            "abs" => Call::Abs,
+            "sin" => Call::Sin,
+            "cos" => Call::Cos,
+            "tanh" => Call::Tanh,
+            "floor" => Call::Floor,
+            _ => return None,
+        })
+    }
+}
+
+impl Expr {
+    // names - parameter names, their position is index into parameters
+    pub fn parse(source: &str, names: &[String]) -> Result<Self, String> {
+        let mut parser = Parser { chars: source.chars().collect(), pos: 0, names };
+        let e = parser.sum()?;
+        match parser.peek() {
+            None => Ok(e),
+            Some(c) => Err(format!("unexpected '{}' at {}", c, parser.pos)),
+        }
+    }
+
+    #[cfg(feature = "arrayfire")]
+    pub fn eval_array(&self, x: &Array<f32>, p: &dyn Fn(usize) -> Array<f32>) -> Array<f32> {
+        match self {
+            Expr::Num(n) => constant(*n, x.dims()),
+            Expr::X => x.clone(),
+            Expr::Param(i) => p(*i),
+            Expr::Neg(a) => -a.eval_array(x, p),
+            Expr::Op(op, a, b) => {
+                let a = a.eval_array(x, p);
+                let b = b.eval_array(x, p);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => pow(&a, &b, false),
+                }
+            },
+            Expr::Call(c, a) => {
+                let a = a.eval_array(x, p);
+                match c {
+                    Call::Exp => exp(&a),
+                    Call::Log => log(&a),
+                    Call::Sqrt => sqrt(&a),
+                    Call::Abs => abs(&a),
+                    Call::Sin => sin(&a),
+                    Call::Cos => cos(&a),
+                    Call::Tanh => tanh(&a),
+                    Call::Floor => floor(&a),
+                }
+            },
+        }
+    }
+
+    pub fn eval(&self, x: f32, p: &[f32]) -> f32 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::X => x,
+            Expr::Param(i) => p[*i],
+            Expr::Neg(a) => -a.eval(x, p),
+            Expr::Op(op, a, b) => {
+                let a = a.eval(x, p);
+                let b = b.eval(x, p);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            },
+            Expr::Call(c, a) => {
+                let a = a.eval(x, p);
+                match c {
+                    Call::Exp => a.exp(),
+                    Call::Log => a.ln(),
+                    Call::Sqrt => a.sqrt(),
+                    Call::Abs => a.abs(),
+                    Call::Sin => a.sin(),
+                    Call::Cos => a.cos(),
+                    Call::Tanh => a.tanh(),
+                    Call::Floor => a.floor(),
+                }
+            },
+        }
+    }
+}
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::X => write!(f, "x"),
+            Expr::Param(i) => write!(f, "p{}", i),
+            Expr::Neg(a) => write!(f, "-({:?})", a),
+            Expr::Op(op, a, b) => write!(f, "({:?} {} {:?})", a, op, b),
+            Expr::Call(c, a) => write!(f, "{:?}({:?})", c, a),
+        }
+    }
+}
+impl fmt::Debug for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Call::Exp => "exp",
+            Call::Log => "log",
+            Call::Sqrt => "sqrt",
+            Call::Abs => "abs",
+            Call::Sin => "sin",
+            Call::Cos => "cos",
+            Call::Tanh => "tanh",
+            Call::Floor => "floor",
+        };
+        write!(f, "{}", l)
+    }
+}
+
+
+// recursive descent, usual precedence, ^ is right associative
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    names: &'a [String],
+}
+impl Parser<'_> {
+    // next character that isn't whitespace
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace() ) { self.pos += 1; }
+        self.chars.get(self.pos).copied()
+    }
+    fn current(&self) -> Option<char> { self.chars.get(self.pos).copied() }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut e = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            e = Expr::Op(op, Box::new(e), Box::new(self.product()?));
+        }
+        Ok(e)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut e = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            e = Expr::Op(op, Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)))
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let e = self.atom()?;
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            return Ok(Expr::Op('^', Box::new(e), Box::new(self.unary()?)))
+        }
+        Ok(e)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let e = self.sum()?;
+                self.expect(')')?;
+                Ok(e)
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.current().is_some_and(|c| c.is_ascii_digit() || c == '.' ) { self.pos += 1; }
+                // exponent, like 1e-3
+                if self.current() == Some('e') && self.chars.get(self.pos + 1).is_some_and(|c| c.is_ascii_digit() || *c == '-' ) {
+                    self.pos += 2;
+                    while self.current().is_some_and(|c| c.is_ascii_digit() ) { self.pos += 1; }
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse::<f32>().map(Expr::Num).map_err(|_| format!("bad number '{}' at {}", text, start) )
+            },
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self.current().is_some_and(|c| c.is_alphanumeric() || c == '_' ) { self.pos += 1; }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if self.peek() == Some('(') {
+                    let call = Call::from_name(&name).ok_or(format!("unknown function '{}' at {}", name, start))?;
+                    self.pos += 1;
+                    let e = self.sum()?;
+                    self.expect(')')?;
+                    return Ok(Expr::Call(call, Box::new(e)))
+                }
+                match name.as_str() {
+                    "x" => Ok(Expr::X),
+                    "pi" => Ok(Expr::Num(std::f32::consts::PI)),
+                    _ => self.names.iter().position(|n| *n == name ).map(Expr::Param)
+                        .ok_or(format!("unknown name '{}' at {}", name, start)),
+                }
+            },
+            Some(c) => Err(format!("unexpected '{}' at {}", c, self.pos)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) { self.pos += 1; Ok(()) }
+        else { Err(format!("expected '{}' at {}", c, self.pos)) }
+    }
+}
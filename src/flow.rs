@@ -0,0 +1,95 @@
+use std::fmt;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use crate::Backend;
+
+
+
+// Flow Lenia, growth field is used as affinity and matter is moved
+// along its gradient with reintegration tracking, so mass is conserved
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Flow {
+    pub sigma: f32,   // half size of the square mass is spread over after moving
+    pub theta: f32,   // total mass at which diffusion takes over
+    pub n: f32,       // how sharply diffusion takes over
+    pub reach: usize, // max cells matter can travel in one step
+}
+impl Default for Flow {
+    fn default() -> Self {
+        Self { sigma: 0.65, theta: 1., n: 2., reach: 5 }
+    }
+}
+impl Flow {
+    // central differences, wrapping around the edges
+    pub fn gradient<B: Backend>(b: &B, x: &B::Matrix, dimensions: usize) -> Vec<B::Matrix> {
+        (0..dimensions).map(|d|{
+            let mut f = [0; 3];
+            let mut r = [0; 3];
+            f[d] = -1;
+            r[d] = 1;
+            b.scale(&b.sub(&b.shift(x, f), &b.shift(x, r)), 0.5)
+        }).collect()
+    }
+
+    // weight of diffusion against affinity
+    pub fn alpha<B: Backend>(&self, b: &B, total: &B::Matrix) -> B::Matrix {
+        let t = b.pow(&b.scale(total, 1. / self.theta), self.n);
+        b.clamp(&t, 0., 1.)
+    }
+
+    // displacement of every cell in a single step
+    pub fn displacement<B: Backend>(
+        &self, b: &B,
+        affinity: &[B::Matrix],
+        total: &[B::Matrix],
+        alpha: &B::Matrix,
+        delta: f32
+    ) -> Vec<B::Matrix> {
+        let max = self.reach as f32 - self.sigma;
+        let beta = b.offset(&b.scale(alpha, -1.), 1.);
+        affinity.iter().zip(total.iter()).map(|(u, a)|{
+            let t = b.scale(&b.sub(&b.mul(u, &beta), &b.mul(a, alpha)), delta);
+            b.clamp(&t, -max, max)
+        }).collect()
+    }
+
+    // every cell sends its mass to a square of side 2*sigma centered at its
+    // new position, receiving cell takes part proportional to the overlap;
+    // walls send nothing and share that would land in them stays in place
+    pub fn reintegrate<B: Backend>(&self, b: &B, matrix: &B::Matrix, displacement: &[B::Matrix], walls: Option<&B::Matrix>) -> B::Matrix {
+        let r = self.reach as isize;
+        let dimensions = displacement.len();
+        let side = self.sigma * 2.;
+        let free = walls.map(|w| b.offset(&b.scale(w, -1.), 1.) );
+        let matrix = match &free { Some(f) => b.mul(matrix, f), None => matrix.clone() };
+        let mut out = b.scale(&matrix, 0.); // keeps worlds stacked along 4th dimension
+
+        let offsets = (0..dimensions).map(|_| -r..=r ).multi_cartesian_product();
+        offsets.for_each(|o|{
+            let mut s = [0; 3];
+            o.iter().enumerate().for_each(|(d, v)| s[d] = *v );
+
+            // share of every cell that goes to the cell o away from it
+            let mut t = matrix.clone();
+            displacement.iter().enumerate().for_each(|(d, disp)|{
+                let dist = b.abs(&b.offset(disp, -(o[d] as f32)));
+                let overlap = b.clamp(&b.offset(&b.scale(&dist, -1.), 0.5 + self.sigma), 0., side.min(1.));
+                t = b.mul(&t, &overlap);
+            });
+            if let Some(f) = &free {
+                let open = b.shift(f, s.map(|v| -v ));
+                let moved = b.mul(&t, &open);
+                out = b.add(&out, &b.sub(&t, &moved));
+                t = moved;
+            }
+            out = b.add(&out, &b.shift(&t, s));
+        });
+
+        b.scale(&out, 1. / side.powi(dimensions as i32))
+    }
+}
+impl fmt::Debug for Flow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "S={}, T={}, N={}, R={}", self.sigma, self.theta, self.n, self.reach)
+    }
+}
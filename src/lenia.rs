@@ -1,40 +1,251 @@
 use std::{collections::HashMap, fmt};
+#[cfg(feature = "arrayfire")]
 use arrayfire::*;
-use serde::{Deserialize, Serialize};
-use crate::{Channel, Layer};
+use itertools::Itertools;
+use crate::{Analytics, Backend, Boundary, Channel, Flow, Integrator, Layer, Noise, Problem, State, Stats};
+#[cfg(feature = "arrayfire")]
+use crate::ArrayFire;
 
 
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Lenia {
-    pub channels: HashMap<usize, Channel>,
-    pub layers: HashMap<usize, Layer>,
+#[derive(Clone)]
+pub struct Lenia<B: Backend> {
+    pub backend: B,
+    pub channels: HashMap<usize, Channel<B>>,
+    pub layers: HashMap<usize, Layer<B>>,
     pub delta: f32,
+    pub integrator: Integrator,
+    pub flow: Flow,
+    pub noise: Noise,
+    pub seed: u64,
+    pub engine: B::Random,
+    pub walls: Option<B::Matrix>, // world mask, 1 - wall, 0 - free
+    pub wall_value: f32,
+    pub wall_opaque: bool, // walls don't contribute mass to convolutions
     pub fitness: f32,  // f>0.25 full; 0>f>0.1 life
-    pub img: Array<f32>,
+    pub step: u64, // steps evaluated since the world was made
+    pub analytics: Analytics, // of the last step
+    pub img: B::Matrix,
+    pub slice: usize, // rendered z-slice of 3d worlds
 }
-impl Lenia {
-    pub fn new(delta: f32, channels: HashMap<usize, Channel>, layers: HashMap<usize, Layer>) -> Self {
-        Self {img: Array::new_empty(Dim4::new(&[1,1,1,1])), 
-            channels, layers, 
-            delta, 
-            fitness: 0.
+impl<B: Backend> Lenia<B> {
+    pub fn new(delta: f32, channels: HashMap<usize, Channel<B>>, layers: HashMap<usize, Layer<B>>) -> Self {
+        let backend = B::default();
+        Self {img: backend.constant(0., [1, 1, 1]),
+            channels, layers,
+            delta,
+            integrator: Integrator::Euler,
+            flow: Flow::default(),
+            noise: Noise::default(),
+            seed: 0,
+            engine: backend.random(0),
+            walls: None, wall_value: 0., wall_opaque: false,
+            fitness: 0.,
+            step: 0,
+            analytics: Analytics::default(),
+            slice: 0,
+            backend,
         }
     }
 
+    // restarts random sequence, so noisy runs can be replayed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.engine = self.backend.random(seed);
+    }
+
+    // runs the world for given number of steps from its matrices and seed, `each` sees it
+    // after every step; search and sweep score their candidates this way
+    pub fn run(mut self, steps: usize, mut each: impl FnMut(&mut Lenia<B>)) -> Result<Self, Vec<Problem>> {
+        let problems = self.validate();
+        if !problems.is_empty() { return Err(problems) }
+        let seed = self.seed;
+        self.reseed(seed);
+        self.init();
+        (0..steps).for_each(|_|{
+            self.evaluate();
+            each(&mut self);
+        });
+        Ok(self)
+    }
+
     pub fn init(&mut self) {
+        let b = self.backend;
+        let channels = &self.channels;
+        self.layers.values_mut().for_each(|l|{
+            l.generate_kernel_lookup(&b);
+            if let Some(ch) = channels.get(&l.inputs()[0].0) { l.prepare(&b, b.dims(&ch.matrix)); }
+        });
+    }
+
+    // makes one step and returns analytics of the new state
+    pub fn evaluate(&mut self) -> Analytics {
+        let b = self.backend;
+        // kernel spectra are kept until world size changes, so stages below only read layers
+        let channels = &self.channels;
         self.layers.values_mut().for_each(|l|{
-            l.generate_kernel_lookup();
+            let Some(ch) = channels.get(&l.inputs()[0].0) else { return };
+            let dims = b.dims(&ch.matrix);
+            if !l.is_prepared(dims) { l.prepare(&b, dims); }
+        });
+
+        let base: State<B> = self.channels.iter().map(|(k,ch)| (*k, ch.matrix.clone()) ).collect();
+        let rate = self.integrator.rate(&b, &base, |state| self.rates(state), |rate, h| self.stage(&base, rate, h) );
+        let mut next = match self.integrator {
+            Integrator::Flow => self.flow_step(&base, &rate),
+            _ => self.stage(&base, &rate, 1.),
+        };
+        if self.noise.changes_update() {
+            // draws follow channel keys, so every run of the same seed is the same
+            next = next.into_iter().sorted_by_key(|(k,_)| *k ).map(|(k,m)|{
+                let m = match base.get(&k) { Some(old) => self.noise.update(&b, old, &m, &self.engine), None => m };
+                (k, m)
+            }).collect();
+        }
+        self.channels.iter_mut().for_each(|(k,ch)|{
+            if let Some(m) = next.remove(k) { ch.matrix = m; }
+            if let Some(r) = rate.get(k) { ch.matrix_out = r.clone(); }
+        });
+        self.pin_walls();
+
+        //// calculate fitness
+        self.fitness = 0.;
+        self.channels.values().for_each(|ch|{
+            let mean = b.sum(&ch.matrix) / b.dims(&ch.matrix).iter().product::<usize>() as f32;
+            self.fitness += mean;
+        });
+        self.fitness /= self.channels.len() as f32;
+        self.step += 1;
+        self.analytics = self.measure();
+        self.analytics.clone()
+    }
+
+    // stats of current state, movement is measured against the previous step
+    fn measure(&self) -> Analytics {
+        let b = &self.backend;
+        let state: State<B> = self.channels.iter().map(|(k,ch)| (*k, ch.matrix.clone()) ).collect();
+        let growth = self.growth(&state);
+        let previous = &self.analytics.channels;
+        let channels = self.channels.iter().filter_map(|(k,ch)|{
+            Some((*k, Stats::measure(b, &ch.matrix, growth.get(k)?, previous.get(k), self.delta)))
+        }).collect();
+        Analytics { step: self.step, channels }
+    }
+
+    // same pattern at different resolution, every matrix is resampled and
+    // every kernel radius scaled by factor
+    pub fn rescale(&mut self, factor: f32, delta: Option<f32>) {
+        let b = self.backend;
+        self.channels.values_mut().for_each(|ch|{
+            ch.matrix = b.resample(&ch.matrix, factor, true);
+            ch.matrix_out = b.constant(0., b.dims(&ch.matrix));
+            ch.walls = ch.walls.as_ref().map(|w| b.resample(w, factor, false) );
+        });
+        self.walls = self.walls.as_ref().map(|w| b.resample(w, factor, false) );
+        self.layers.values_mut().for_each(|l|{
+            l.radius = ((l.radius as f32 * factor).round() as usize).max(1);
+            l.parameter_maps.values_mut().for_each(|m| *m = b.resample(m, factor, true) );
+            l.kernel_fft = None;
+            l.generate_kernel_lookup(&b);
+        });
+        if let Some(d) = delta { self.delta = d; }
+        // old centroids would show up as a jump
+        self.analytics.channels.clear();
+    }
+
+    // growth of every channel with noise on top, draws follow channel keys
+    fn rates(&self, state: &State<B>) -> State<B> {
+        let b = &self.backend;
+        self.growth(state).into_iter().sorted_by_key(|(c,_)| *c ).map(|(c,g)| (c, self.noise.growth(b, &g, &self.engine)) ).collect()
+    }
+
+    // run layers on given state and sum weighted outputs into growth of every channel
+    fn growth(&self, state: &State<B>) -> State<B> {
+        let b = &self.backend;
+        // every channel is transformed once and shared between layers reading it
+        let mut spectra: HashMap<(usize, Boundary, usize, usize), B::Spectrum> = HashMap::new();
+        let outputs: HashMap<usize, B::Matrix> = self.layers.iter().filter_map(|(k,l)|{
+            let inputs = l.inputs();
+            let dims = b.dims(state.get(&inputs[0].0)?);
+            for (c,_) in inputs.iter() {
+                let m = state.get(c)?;
+                spectra.entry(l.spectrum_key(*c, dims)).or_insert_with(||{
+                    let walls = self.channels.get(c).and_then(|ch| wall_mask(b, &self.walls, &ch.walls) );
+                    match walls {
+                        Some(w) if self.wall_opaque => l.transform(b, &b.mul(m, &b.offset(&b.scale(&w, -1.), 1.))),
+                        _ => l.transform(b, m),
+                    }
+                });
+            }
+            let input = inputs.iter().filter_map(|(c,w)| Some((spectra.get(&l.spectrum_key(*c, dims))?, *w)) ).collect::<Vec<_>>();
+            Some((*k, l.run(b, &input, dims)))
+        }).collect();
+
+        state.iter().map(|(c,m)|{
+            let weights = self.channels.get(c).map(|ch| ch.weights.iter().sorted_by_key(|(l,_)| **l ).collect::<Vec<_>>() ).unwrap_or_default();
+            let sum = weights.iter().filter_map(|(l,w)| Some(b.scale(outputs.get(l)?, **w)) )
+                .fold(b.constant(0., b.dims(m)), |s, t| b.add(&s, &t) );
+            (*c, sum)
+        }).collect()
+    }
+
+    // every channel moved to intermediate state base + h*delta*rate
+    fn stage(&self, base: &State<B>, rate: &State<B>, h: f32) -> State<B> {
+        let b = &self.backend;
+        base.iter().map(|(k,m)|{
+            let mut t = match rate.get(k) { Some(r) => b.clamp(&b.add(m, &b.scale(r, h * self.delta)), 0., 1.), None => m.clone() };
+            if let Some(w) = self.channels.get(k).and_then(|ch| wall_mask(b, &self.walls, &ch.walls) ) {
+                t = b.add(&b.mul(&t, &b.offset(&b.scale(&w, -1.), 1.)), &b.scale(&w, self.wall_value));
+            }
+            (*k, t)
+        }).collect()
+    }
+
+    // matter of every channel moved along gradient of its growth
+    fn flow_step(&self, base: &State<B>, rate: &State<B>) -> State<B> {
+        let b = &self.backend;
+        let Some(dims) = base.values().next().map(|m| b.dims(m) ) else { return HashMap::new() };
+        let n = if dims[2] > 1 { 3 } else { 2 };
+        let total = base.values().fold(b.constant(0., dims), |t, m| b.add(&t, m) );
+        let alpha = self.flow.alpha(b, &total);
+        let total = Flow::gradient(b, &total, n);
+        base.iter().map(|(k,m)|{
+            let Some(r) = rate.get(k) else { return (*k, m.clone()) };
+            let affinity = Flow::gradient(b, r, n);
+            let displacement = self.flow.displacement(b, &affinity, &total, &alpha, self.delta);
+            let walls = self.channels.get(k).and_then(|ch| wall_mask(b, &self.walls, &ch.walls) );
+            (*k, self.flow.reintegrate(b, m, &displacement, walls.as_ref()))
+        }).collect()
+    }
+
+    fn pin_walls(&mut self) {
+        let b = self.backend;
+        let walls = &self.walls;
+        let value = self.wall_value;
+        self.channels.values_mut().for_each(|ch|{
+            if let Some(w) = wall_mask(&b, walls, &ch.walls) { ch.pin(&w, value); }
         });
     }
-    
+
+}
+
+#[cfg(feature = "arrayfire")]
+impl Lenia<ArrayFire> {
     pub fn generate_image(&mut self) {
-        let matrix = &self.channels.values().next().unwrap().matrix;
+        let ch = self.channels.values().next().unwrap();
+        // volumes are rendered one z-slice at a time
+        let depth = ch.matrix.dims()[2] as usize;
+        self.slice = self.slice.min(depth - 1);
+        let matrix = &slice(&ch.matrix, self.slice as i64);
         let r = color_bump(matrix, 1.);
         let g = color_bump(matrix, 1.5);
         let b = color_bump(matrix, 2.1);
         self.img = join_many(2, vec![&r,&g,&b]);
-        
+        if let Some(w) = wall_mask(&ArrayFire, &self.walls, &ch.walls) {
+            let w = tile(&slice(&w, self.slice as i64), Dim4::new(&[1,1,3,1]));
+            self.img = &self.img * sub(&1_f32, &w, false) + w * 0.5_f32;
+        }
+
         fn color_bump(x: &Array<f32>, offset: f32) -> Array<f32> {
             let mut t = x * 3_f32;
             t = &t - offset;
@@ -44,46 +255,45 @@ impl Lenia {
         }
     }
 
-    pub fn evaluate(&mut self) {
-        self.layers.values_mut().for_each(|l|{
-            l.run(self.channels.get(&l.source_key).unwrap());
-        });
-
-        self.channels.values_mut().for_each(|ch|{
-            // sum layers outputs
-            let dims = self.layers.get( ch.weights.keys().next().unwrap() ).unwrap().matrix_out.dims();
-            ch.matrix_out = Array::new_empty(dims);
+    // rendered image as png, size - longer side of scaled down copy
+    pub fn save_image(&mut self, path: &str, size: Option<u64>) {
+        self.generate_image();
+        let mut img = clamp(&self.img, &0_f32, &1_f32, false) * 255_f32;
+        if let Some(s) = size {
+            let d = img.dims();
+            let f = s as f32 / d[0].max(d[1]) as f32;
+            img = resize(&img, (d[0] as f32 * f).max(1.) as i64, (d[1] as f32 * f).max(1.) as i64, InterpType::BILINEAR);
+        }
+        save_image_native(path.to_string(), &img.cast::<u8>());
+    }
+}
 
-            ch.weights.iter().for_each(|(k,w)|{
-                let t = &self.layers.get(k).unwrap().matrix_out * (*w);
-                ch.matrix_out = &ch.matrix_out + t; // add to output matrix
-            });
 
-            ch.finish(self.delta);
-        });
 
-        //// calculate fitness
-        self.fitness = 0.;
-        self.channels.values().for_each(|ch|{
-            let (sum, _) = sum_all(&ch.matrix);
-            let mean = sum / ch.matrix.elements() as f32;
-            self.fitness += mean;
-        });
-        self.fitness /= self.channels.len() as f32;
+// world and channel masks combined
+fn wall_mask<B: Backend>(b: &B, world: &Option<B::Matrix>, channel: &Option<B::Matrix>) -> Option<B::Matrix> {
+    match (world, channel) {
+        (Some(x), Some(y)) => Some(b.max(x, y)),
+        (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+        (None, None) => None,
     }
-
 }
 
 
-
-impl PartialEq for Lenia {
+impl<B: Backend> PartialEq for Lenia<B> {
     fn eq(&self, other: &Self) -> bool {
         self.channels == other.channels &&
         self.layers == other.layers &&
-        self.delta == other.delta
+        self.delta == other.delta &&
+        self.integrator == other.integrator &&
+        self.flow == other.flow &&
+        self.noise == other.noise &&
+        self.seed == other.seed &&
+        self.wall_value == other.wall_value &&
+        self.wall_opaque == other.wall_opaque
     }
 }
-impl fmt::Debug for Lenia {
+impl<B: Backend> fmt::Debug for Lenia<B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut l = "<<<ECO>>>".to_string();
         l += "\nCh: \n";
@@ -92,9 +302,12 @@ impl fmt::Debug for Lenia {
         l += &format!("{:?}", self.layers);
         l += "\nDelta: ";
         l += &self.delta.to_string();
+        l += "\nIntegrator: ";
+        l += &self.integrator.to_string();
+        if self.integrator == Integrator::Flow { l += &format!(" {:?}", self.flow); }
+        l += &format!("\nNoise: {:?} Seed: {}", self.noise, self.seed);
         l += "\nFitness: ";
         l += &self.fitness.to_string();
         write!(f, "{}", l)
     }
 }
-
@@ -1,11 +1,53 @@
 mod misc;
-pub use misc::{Function, Shape, FrameTimeAnalyzer, Cycle};
+pub use misc::{Function, Shape, Core, Integrator, Boundary, Mix, FrameTimeAnalyzer, Cycle};
+
+mod expression;
+pub use expression::{Formula, Expr};
+
+mod anisotropy;
+pub use anisotropy::Anisotropy;
+
+mod backend;
+pub use backend::{Backend, Cpu, Grid, Spectrum, State};
+#[cfg(feature = "arrayfire")]
+pub use backend::ArrayFire;
 
 mod bbs;
 pub use bbs::{Channel, Layer};
 
+mod flow;
+pub use flow::Flow;
+
+mod noise;
+pub use noise::Noise;
+
+mod analytics;
+pub use analytics::{Analytics, Stats};
+
+mod tracking;
+pub use tracking::{Creature, Event, Tracker, label};
+#[cfg(feature = "arrayfire")]
+pub use tracking::segment;
+
 mod lenia;
 pub use lenia::Lenia;
 
+mod validation;
+pub use validation::Problem;
+
+#[cfg(feature = "arrayfire")]
+mod batch;
+#[cfg(feature = "arrayfire")]
+pub use batch::Batch;
+
+mod preset;
+pub use preset::{Cause, PresetError, Presets};
+
 mod logger;
-pub use logger::{DataLenia, PackageLenia};
+pub use logger::{DataChannel, DataLayer, DataLenia, PackageLenia};
+
+mod search;
+pub use search::{Fitness, Search};
+
+mod sweep;
+pub use sweep::{Axis, Outcome, Sweep, Target};
@@ -1,8 +1,9 @@
-use std::{collections::HashMap, fs::{self, File}, io::{Read, Write}, vec};
-use arrayfire::{Array, Dim4};
+use std::{collections::HashMap, fs};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use crate::{Channel, Function, Layer, Lenia, Shape};
+use crate::{Anisotropy, Backend, Boundary, Flow, Function, Integrator, Noise, Mix};
+use crate::{Channel, Layer, Lenia, Problem, Shape};
+use crate::{Cause, PresetError, preset::{create_dir, entries, read_bin, read_toml, write_bin, write_toml}};
 
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -13,9 +14,9 @@ pub struct PackageLenia {
 }
 impl PackageLenia {
     pub fn empty() -> Self {
-        Self { lenia: DataLenia{ delta: 0.1}, layers: vec![], channels: vec![] }
+        Self { lenia: DataLenia{ delta: 0.1, ..Default::default() }, layers: vec![], channels: vec![] }
     }
-    pub fn from_lenia(lenia: &Lenia) -> Self {
+    pub fn from_lenia<B: Backend>(lenia: &Lenia<B>) -> Self {
         let l = DataLenia::new(lenia);
         let layers: Vec<(usize, DataLayer)> = 
             lenia.layers.iter().map(|(k,l)| (*k, DataLayer::new(l)) ).collect();
@@ -27,39 +28,62 @@ impl PackageLenia {
             channels 
         }
     }
-    pub fn update_lenia(package: &Self, lenia: &mut Lenia) {
-        lenia.delta = package.lenia.delta;
+    // changes are applied only when resulting lenia is valid
+    pub fn update_lenia<B: Backend>(package: &Self, lenia: &mut Lenia<B>) -> Result<(), Vec<Problem>> {
+        let problems = package.layers.iter().filter_map(|(k,l)| l.unpaired(*k) ).collect::<Vec<_>>();
+        if !problems.is_empty() { return Err(problems) }
+
+        let mut updated = lenia.clone();
+        updated.delta = package.lenia.delta;
+        updated.integrator = package.lenia.integrator;
+        updated.flow = package.lenia.flow;
+        updated.noise = package.lenia.noise;
+        updated.wall_value = package.lenia.wall_value;
+        updated.wall_opaque = package.lenia.wall_opaque;
         package.layers.iter().for_each(|(k,l)|{
-            let test = lenia.layers.get(k).is_some();
-            let layer = if test {lenia.layers.get_mut(k).unwrap()} else {
-                let copy = lenia.layers.iter().next().unwrap().1.clone();
-                lenia.layers.insert(*k, copy).unwrap();
-                lenia.layers.get_mut(k).unwrap()
-            };
+            let layer = updated.layers.entry(*k).or_insert_with(||
+                Layer::new(l.kernel.clone(), l.growth_map.clone(), l.source_key, l.radius)
+            );
 
             layer.source_key = l.source_key;
+            layer.sources = l.sources.iter().copied().zip(l.source_weights.iter().copied()).collect();
+            layer.mix = l.mix;
             layer.kernel = l.kernel.clone();
             if layer.kernel.shape == Shape::GaussianBumpMulti {
-                while layer.kernel.parameters.len() % 3 != 0 {layer.kernel.parameters.push(1.);}
+                while !layer.kernel.parameters.len().is_multiple_of(3) {layer.kernel.parameters.push(1.);}
             }
             layer.growth_map = l.growth_map.clone();
             if layer.growth_map.shape == Shape::GaussianBumpMulti {
-                while layer.growth_map.parameters.len() % 3 != 0 {layer.growth_map.parameters.push(1.);}
+                while !layer.growth_map.parameters.len().is_multiple_of(3) {layer.growth_map.parameters.push(1.);}
             }
             layer.radius = l.radius;
-            layer.generate_kernel_lookup();
+            layer.boundary = l.boundary;
+            layer.dimensions = l.dimensions;
+            layer.anisotropy = l.anisotropy;
         });
         package.channels.iter().for_each(|(k,ch)|{
-            let test = lenia.channels.get(k).is_some();
-            let channel = if test {lenia.channels.get_mut(k).unwrap()} else {
-                let copy = lenia.channels.iter().next().unwrap().1.clone();
-                lenia.channels.insert(*k, copy).unwrap();
-                lenia.channels.get_mut(k).unwrap()
-            };
+            if !updated.channels.contains_key(k) {
+                let Some(copy) = updated.channels.values().next().cloned() else { return };
+                updated.channels.insert(*k, copy);
+            }
+            let Some(channel) = updated.channels.get_mut(k) else { return };
             ch.keys.iter().zip(ch.floats.iter()).for_each(|(dk,f)|{
-                *channel.weights.get_mut(dk).unwrap() = *f;
+                channel.weights.insert(*dk, *f);
             });
         });
+
+        let problems = updated.validate();
+        if !problems.is_empty() { return Err(problems) }
+
+        let b = updated.backend;
+        package.layers.iter().for_each(|(k,_)|{
+            if let Some(l) = updated.layers.get_mut(k) { l.generate_kernel_lookup(&b); }
+        });
+        // noise goes on from where it got to, unless seed was changed
+        if updated.seed == package.lenia.seed { std::mem::swap(&mut updated.engine, &mut lenia.engine); }
+        else { updated.reseed(package.lenia.seed); }
+        *lenia = updated;
+        Ok(())
     }
 }
 
@@ -67,9 +91,21 @@ impl PackageLenia {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct DataLayer {
     pub source_key: usize,
+    #[serde(default)]
+    pub sources: Vec<usize>, // channel key, weight; used instead of source_key when not empty
+    #[serde(default)]
+    pub source_weights: Vec<f32>,
+    #[serde(default)]
+    pub mix: Mix,
     pub kernel: Function,
     pub growth_map: Function,
     pub radius: usize,
+    #[serde(default)]
+    pub boundary: Boundary,
+    #[serde(default = "DataLayer::default_dimensions")]
+    pub dimensions: usize,
+    #[serde(default)]
+    pub anisotropy: Anisotropy,
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct DataMatrix {}
@@ -79,78 +115,117 @@ pub struct DataChannel {
     pub floats: Vec<f32>
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct DataLenia {
     pub delta: f32,
+    #[serde(default)]
+    pub integrator: Integrator,
+    #[serde(default)]
+    pub flow: Flow,
+    #[serde(default)]
+    pub noise: Noise,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub wall_value: f32,
+    #[serde(default)]
+    pub wall_opaque: bool, // walls don't contribute to convolutions
 }
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataWalls {}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataMaps {}
 
 impl DataLenia {
-    fn new(lenia: &Lenia) -> Self {
-        Self {delta: lenia.delta}
+    fn new<B: Backend>(lenia: &Lenia<B>) -> Self {
+        Self {
+            delta: lenia.delta,
+            integrator: lenia.integrator,
+            flow: lenia.flow,
+            noise: lenia.noise,
+            seed: lenia.seed,
+            wall_value: lenia.wall_value,
+            wall_opaque: lenia.wall_opaque,
+        }
     }
 
-    pub fn save(key: usize, lenia: &Lenia) {
-        let mut path = "data/".to_string();
-        path += &key.to_string();
-        path += "/";
-        let _ = fs::remove_dir_all(&path);
-        fs::create_dir_all(path.clone() + "matrix").unwrap();
-        fs::create_dir_all(path.clone() + "channel").unwrap();
-        fs::create_dir_all(path.clone() + "layer").unwrap();
+    // previous preset in dir is replaced, other files are left alone;
+    // non-empty directory without lenia.toml isn't a preset and is refused
+    pub fn save<B: Backend>(dir: &str, lenia: &Lenia<B>) -> Result<(), PresetError> {
+        let dir = dir.trim_end_matches('/');
+        let path = format!("{}/", dir);
+        if let Ok(mut d) = fs::read_dir(dir) {
+            if d.next().is_some() && fs::metadata(path.clone() + "lenia.toml").is_err() {
+                return Err(PresetError::new(dir, Cause::Occupied))
+            }
+        }
+        ["matrix", "channel", "layer", "walls", "maps"].iter().for_each(|d|{ let _ = fs::remove_dir_all(path.clone() + d); });
+        let _ = fs::remove_file(path.clone() + "walls.bin");
+        ["matrix", "channel", "layer", "walls"].iter().try_for_each(|d| create_dir(&(path.clone() + d)) )?;
 
-        let data_lenia = DataLenia {delta: lenia.delta };
-        let toml = toml::to_string(&data_lenia).unwrap();
-        let mut file = File::create(path + "lenia.toml").unwrap();
-        file.write(toml.as_bytes()).unwrap();
+        write_toml(&(path + "lenia.toml"), &DataLenia::new(lenia))?;
 
-        lenia.layers.iter().sorted_by(|a,b| Ord::cmp(a.0, b.0) ).for_each(|(k,l)|{
-            DataLayer::save(key, *k, &l);
-        });
+        lenia.layers.iter().sorted_by(|a,b| Ord::cmp(a.0, b.0) ).try_for_each(|(k,l)|{
+            DataLayer::save(dir, *k, l)
+        })?;
         
-        lenia.channels.iter().sorted_by(|a,b| Ord::cmp(a.0, b.0) ).for_each(|(k,c)|{
-            DataMatrix::save(key, *k, &c);
-            DataChannel::save(key, *k, &c);
-        });
+        lenia.channels.iter().sorted_by(|a,b| Ord::cmp(a.0, b.0) ).try_for_each(|(k,c)|{
+            DataMatrix::save(dir, *k, c)?;
+            DataChannel::save(dir, *k, c)?;
+            if let Some(w) = &c.walls { DataWalls::save::<B>(dir, Some(*k), w)?; }
+            Ok(())
+        })?;
+        if let Some(w) = &lenia.walls { DataWalls::save::<B>(dir, None, w)?; }
+        Ok(())
     }
-    pub fn load(key: usize) -> Lenia {
-        let mut path = "data/".to_string();
-        path += &key.to_string();
-        path += "/";
-        
-        let mut toml = String::new();
-        let mut file = File::open(path.clone() + "lenia.toml").unwrap();
-        file.read_to_string(&mut toml).unwrap();
-        let decoded: Self = toml::from_str(&toml).unwrap();
-        let mut lenia = Lenia::new(decoded.delta, HashMap::new(), HashMap::new());
+    // every part of the preset is read and checked with Lenia::validate
+    pub fn load<B: Backend>(dir: &str) -> Result<Lenia<B>, PresetError> {
+        let dir = dir.trim_end_matches('/');
+        let path = format!("{}/", dir);
         
+        let decoded: Self = read_toml(&(path.clone() + "lenia.toml"))?;
+        let mut lenia = Lenia::<B>::new(decoded.delta, HashMap::new(), HashMap::new());
+        lenia.integrator = decoded.integrator;
+        lenia.flow = decoded.flow;
+        lenia.noise = decoded.noise;
+        lenia.reseed(decoded.seed);
+        lenia.wall_value = decoded.wall_value;
+        lenia.wall_opaque = decoded.wall_opaque;
         
-        let dir = fs::read_dir(path.clone() + "layer/" ).unwrap();
-        let entries = dir.map(|res| res.unwrap().file_name().into_string().unwrap() ).collect::<Vec<_>>();
-        entries.iter().for_each(|e|{
-            let layer = DataLayer::load(key, e.replace(".toml", "").parse::<usize>().unwrap());
-            lenia.layers.insert(e.replace(".toml", "").parse::<usize>().unwrap(), layer);
-        });
-        
-        let dir = fs::read_dir(path.clone() + "matrix/" ).unwrap();
-        let entries = dir.map(|res| res.unwrap().file_name().into_string().unwrap() ).collect::<Vec<_>>();
-        entries.iter().for_each(|e|{
-            let channel = DataMatrix::load(key, e.replace(".bin", "").parse::<usize>().unwrap());
-            lenia.channels.insert(e.replace(".bin", "").parse::<usize>().unwrap(), channel);
-        });
-        let dir = fs::read_dir(path.clone() + "channel/" ).unwrap();
-        let entries = dir.map(|res| res.unwrap().file_name().into_string().unwrap() ).collect::<Vec<_>>();
-        entries.iter().for_each(|e|{
-            let channel = DataChannel::load(key, e.replace(".toml", "").parse::<usize>().unwrap());
-            lenia.channels.get_mut( &e.replace(".toml", "").parse::<usize>().unwrap() ).unwrap().weights = channel.weights;
-        });
+        for (k, _) in entries(&(path.clone() + "layer"), ".toml")? {
+            lenia.layers.insert(k, DataLayer::load::<B>(dir, k)?);
+        }
+        for (k, _) in entries(&(path.clone() + "matrix"), ".bin")? {
+            lenia.channels.insert(k, DataMatrix::load::<B>(dir, k)?);
+        }
+        for (k, p) in entries(&(path.clone() + "channel"), ".toml")? {
+            let weights = DataChannel::load(dir, k)?;
+            let channel = lenia.channels.get_mut(&k)
+                .ok_or_else(|| PresetError::new(&p, Cause::Missing(format!("matrix/{}.bin of channel {}", k, k))) )?;
+            channel.weights = weights;
+        }
 
-        lenia
+        // walls are optional, older presets don't have them
+        lenia.walls = DataWalls::load::<B>(dir, None)?;
+        if fs::metadata(path.clone() + "walls").is_ok() {
+            for (k, p) in entries(&(path.clone() + "walls"), ".bin")? {
+                let walls = DataWalls::load::<B>(dir, Some(k))?;
+                let channel = lenia.channels.get_mut(&k)
+                    .ok_or_else(|| PresetError::new(&p, Cause::Missing(format!("channel {} of walls", k))) )?;
+                channel.walls = walls;
+            }
+        }
+
+        // preset that loads has to be safe to evaluate
+        let problems = lenia.validate();
+        if !problems.is_empty() { return Err(PresetError::new(dir, Cause::Invalid(problems.iter().join(", ")))) }
+        Ok(lenia)
     }
 }
 
 
 impl DataChannel {
-    fn new(channel: &Channel) -> Self {
+    fn new<B: Backend>(channel: &Channel<B>) -> Self {
         let mut ch = Self {
             keys: vec![],
             floats: vec![]
@@ -161,128 +236,136 @@ impl DataChannel {
         });
         ch
     }
-    fn save(lenia_key: usize, key: usize, channel: &Channel) {
-        let mut ch = Self {
-            keys: vec![],
-            floats: vec![]
-        };
-
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/channel/";
-        path += &key.to_string();
-        path += ".toml";
-        
-        channel.weights.iter().for_each(|(k,w)|{
-            ch.keys.push(*k);
-            ch.floats.push(*w);
-        });
-        let toml = toml::to_string(&ch).unwrap();
-        let mut file = File::create(path).unwrap();
-        file.write(toml.as_bytes()).unwrap();
+    fn save<B: Backend>(dir: &str, key: usize, channel: &Channel<B>) -> Result<(), PresetError> {
+        write_toml(&format!("{}/channel/{}.toml", dir, key), &Self::new(channel))
     }
-    fn load(lenia_key: usize, key: usize) -> Channel {
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/channel/";
-        path += &key.to_string();
-        path += ".toml";
-        
-        let mut toml = String::new();
-        let mut file = File::open(path).unwrap();
-        
-        file.read_to_string(&mut toml).unwrap();
-        let decoded: Self = toml::from_str(&toml).unwrap();
+    fn load(dir: &str, key: usize) -> Result<HashMap<usize, f32>, PresetError> {
+        let decoded: Self = read_toml(&format!("{}/channel/{}.toml", dir, key))?;
         
         let mut weights: HashMap<usize, f32> = HashMap::new(); 
         decoded.keys.iter().zip(decoded.floats.iter()).for_each(|(k, w)|{
             weights.insert(*k, *w);
         });
-
-        Channel { 
-            matrix: Array::new_empty(Dim4::new(&[1,1,1,1])), 
-            matrix_out: Array::new_empty(Dim4::new(&[1,1,1,1])), 
-            weights 
-        }
+        Ok(weights)
     }
 }
 
 impl DataMatrix {
-    fn save(lenia_key: usize, key: usize, channel: &Channel) {
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/matrix/";
-        path += &key.to_string();
-        path += ".bin";
-        let encoded: Vec<u8> = bincode::serialize(&channel.matrix).unwrap();
-        let mut file = File::create(path).unwrap();
-        file.write(&encoded).unwrap();
+    fn save<B: Backend>(dir: &str, key: usize, channel: &Channel<B>) -> Result<(), PresetError> {
+        write_matrix::<B>(&format!("{}/matrix/{}.bin", dir, key), &channel.matrix)
     }
-    fn load(lenia_key: usize, key: usize) -> Channel {
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/matrix/";
-        path += &key.to_string();
-        path += ".bin";
-        let mut file = File::open(path).unwrap();
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer).unwrap();
-        let matrix: Array<f32> = bincode::deserialize(&buffer).unwrap();
+    fn load<B: Backend>(dir: &str, key: usize) -> Result<Channel<B>, PresetError> {
+        Ok(Channel::new(read_matrix::<B>(&format!("{}/matrix/{}.bin", dir, key))?))
+    }
+}
 
-        Channel { matrix: matrix.copy(), matrix_out: matrix, weights: HashMap::new() }
+impl DataWalls {
+    // world mask is stored as walls.bin, channel masks as walls/<key>.bin
+    fn path(dir: &str, key: Option<usize>) -> String {
+        match key {
+            Some(k) => format!("{}/walls/{}.bin", dir, k),
+            None => format!("{}/walls.bin", dir),
+        }
+    }
+    fn save<B: Backend>(dir: &str, key: Option<usize>, walls: &B::Matrix) -> Result<(), PresetError> {
+        write_matrix::<B>(&Self::path(dir, key), walls)
+    }
+    fn load<B: Backend>(dir: &str, key: Option<usize>) -> Result<Option<B::Matrix>, PresetError> {
+        let path = Self::path(dir, key);
+        if fs::metadata(&path).is_err() { return Ok(None) }
+        read_matrix::<B>(&path).map(Some)
+    }
+}
+
+impl DataMaps {
+    // growth parameter maps are stored as maps/<layer key>/<parameter index>.bin
+    fn path(dir: &str, key: usize) -> String {
+        format!("{}/maps/{}/", dir, key)
+    }
+    fn save<B: Backend>(dir: &str, key: usize, maps: &HashMap<usize, B::Matrix>) -> Result<(), PresetError> {
+        if maps.is_empty() { return Ok(()) }
+        let path = Self::path(dir, key);
+        create_dir(&path)?;
+        maps.iter().try_for_each(|(i,m)| write_matrix::<B>(&format!("{}{}.bin", path, i), m) )
+    }
+    fn load<B: Backend>(dir: &str, key: usize) -> Result<HashMap<usize, B::Matrix>, PresetError> {
+        let path = Self::path(dir, key);
+        if fs::metadata(&path).is_err() { return Ok(HashMap::new()) }
+        entries(&path, ".bin")?.into_iter().map(|(i,p)| Ok((i, read_matrix::<B>(&p)?)) ).collect()
     }
 }
 
 impl DataLayer {
-    fn new(layer: &Layer) -> Self {
+    fn default_dimensions() -> usize { 2 }
+}
+impl DataLayer {
+    // sources and their weights are separate lists, extra entries of the longer one would be dropped
+    fn unpaired(&self, key: usize) -> Option<Problem> {
+        if self.sources.len() == self.source_weights.len() { return None }
+        Some(Problem::SourceWeights { layer: key, sources: self.sources.len(), weights: self.source_weights.len() })
+    }
+
+    fn new<B: Backend>(layer: &Layer<B>) -> Self {
         Self {
             source_key: layer.source_key,
+            sources: layer.sources.keys().sorted().copied().collect(),
+            source_weights: layer.sources.iter().sorted_by_key(|(k,_)| **k ).map(|(_,w)| *w ).collect(),
+            mix: layer.mix,
             kernel: layer.kernel.clone(),
             growth_map: layer.growth_map.clone(),
-            radius: layer.radius
+            radius: layer.radius,
+            boundary: layer.boundary,
+            dimensions: layer.dimensions,
+            anisotropy: layer.anisotropy,
         }
     }
-    fn save(lenia_key: usize, key: usize, layer: &Layer) {
-        let tl = Self {
-            source_key: layer.source_key,
-            kernel: layer.kernel.clone(),
-            growth_map: layer.growth_map.clone(),
-            radius: layer.radius
-        };
-
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/layer/";
-        path += &key.to_string();
-        path += ".toml";
+    fn save<B: Backend>(dir: &str, key: usize, layer: &Layer<B>) -> Result<(), PresetError> {
+        write_toml(&format!("{}/layer/{}.toml", dir, key), &Self::new(layer))?;
+        DataMaps::save::<B>(dir, key, &layer.parameter_maps)
+    }
+    fn load<B: Backend>(dir: &str, key: usize) -> Result<Layer<B>, PresetError> {
+        let path = format!("{}/layer/{}.toml", dir, key);
+        let decoded: DataLayer = read_toml(&path)?;
+        if let Some(p) = decoded.unpaired(key) { return Err(PresetError::new(&path, Cause::Invalid(p.to_string()))) }
 
-        let toml = toml::to_string(&tl).unwrap();
-        let mut file = File::create(path).unwrap();
-        file.write(toml.as_bytes()).unwrap();
+        let mut layer = Layer::new(decoded.kernel, decoded.growth_map, decoded.source_key, decoded.radius);
+        layer.parameter_maps = DataMaps::load::<B>(dir, key)?;
+        layer.sources = decoded.sources.iter().copied().zip(decoded.source_weights.iter().copied()).collect();
+        layer.mix = decoded.mix;
+        layer.boundary = decoded.boundary;
+        layer.dimensions = decoded.dimensions;
+        layer.anisotropy = decoded.anisotropy;
+        // broken kernel is reported by validation instead
+        if layer.kernel.check().is_ok() { layer.generate_kernel_lookup(&B::default()); }
+        Ok(layer)
     }
-    fn load(lenia_key: usize, key: usize) -> Layer {
-        let mut path = "data/".to_string();
-        path += &lenia_key.to_string();
-        path += "/layer/";
-        path += &key.to_string();
-        path += ".toml";
-        
-        let mut toml = String::new();
-        let mut file = File::open(path).unwrap();
+}
 
-        file.read_to_string(&mut toml).unwrap();
-        let decoded: DataLayer = toml::from_str(&toml).unwrap();
 
-        let mut layer = Layer { 
-            kernel: decoded.kernel,
-            kernel_lookup: Array::new_empty(Dim4::new(&[1,1,1,1])), 
-            growth_map: decoded.growth_map, 
-            source_key: decoded.source_key, 
-            matrix_out: Array::new_empty(Dim4::new(&[1,1,1,1])), 
-            radius: decoded.radius 
-        };
-        layer.generate_kernel_lookup();
-        layer
-    }
+// layout of arrayfire matrix written by bincode (afserde), so presets don't depend on backend
+#[derive(Serialize, Deserialize)]
+struct HostArray {
+    dtype: u32, // 0 - f32
+    shape: HostDims,
+    data: Vec<f32>,
+}
+#[derive(Serialize, Deserialize)]
+struct HostDims {
+    dims: [u64; 4],
 }
 
+fn read_matrix<B: Backend>(path: &str) -> Result<B::Matrix, PresetError> {
+    let m: HostArray = read_bin(path)?;
+    if m.dtype != 0 { return Err(PresetError::new(path, Cause::Unsupported("matrix type other than f32".to_string()))) }
+    let d = m.shape.dims.map(|n| n as usize );
+    if d.iter().product::<usize>() != m.data.len() || d[3] != 1 {
+        return Err(PresetError::new(path, Cause::Invalid(format!("matrix of size {:?} has {} values", d, m.data.len()))))
+    }
+    Ok(B::default().matrix(&m.data, [d[0], d[1], d[2]]))
+}
+fn write_matrix<B: Backend>(path: &str, matrix: &B::Matrix) -> Result<(), PresetError> {
+    let b = B::default();
+    let d = b.dims(matrix);
+    let m = HostArray { dtype: 0, shape: HostDims { dims: [d[0] as u64, d[1] as u64, d[2] as u64, 1] }, data: b.host(matrix) };
+    write_bin(path, &m)
+}
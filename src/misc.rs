@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 use serde::{Deserialize, Serialize};
 use std::fmt;
+#[cfg(feature = "arrayfire")]
+use std::collections::HashMap;
+#[cfg(feature = "arrayfire")]
 use arrayfire::*;
+use itertools::Itertools;
+use crate::{Backend, Formula, State};
 
 pub trait Cycle {
     fn next(&mut self);
@@ -14,6 +19,50 @@ pub enum Shape {
     GaussianBumpMulti,
     ExponentialDecay,
     SmoothTransition,
+    Rings(Core), // peak height of every ring
+    Table,       // x0, y0, x1, y1, ... linearly interpolated, x ascending
+    Expression(Formula),
+}
+
+// shape of single ring, Bert Chan's kernel cores
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Core {
+    Exponential,
+    Polynomial,
+    Rectangular,
+}
+impl Core {
+    // r in 0..1 of single ring
+    #[cfg(feature = "arrayfire")]
+    pub fn calc_array(&self, r: &Array<f32>) -> Array<f32> {
+        let t = r * &sub(&1_f32, r, false);
+        match self {
+            Core::Exponential => exp(&(4_f32 - 1_f32 / t)),
+            Core::Polynomial => pow(&(t * 4_f32), &4_f32, false),
+            Core::Rectangular => {
+                let t = and(&ge(r, &0.25_f32, false), &le(r, &0.75_f32, false), false);
+                t.cast::<f32>()
+            },
+        }
+    }
+    pub fn _calc(&self, r: f32) -> f32 {
+        let t = r * (1. - r);
+        match self {
+            Core::Exponential => if t > 0. { (4. - 1. / t).exp() } else { 0. },
+            Core::Polynomial => (4. * t).powi(4),
+            Core::Rectangular => if (0.25..=0.75).contains(&r) { 1. } else { 0. },
+        }
+    }
+}
+impl fmt::Display for Core {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Self::Exponential => "Exponential",
+            Self::Polynomial => "Polynomial",
+            Self::Rectangular => "Rectangular",
+        };
+        write!(f, "{}", l)
+    }
 }
 
 impl Cycle for Shape {
@@ -22,47 +71,244 @@ impl Cycle for Shape {
             Shape::GaussianBump => Shape::GaussianBumpMulti,
             Shape::GaussianBumpMulti => Shape::ExponentialDecay,
             Shape::ExponentialDecay => Shape::SmoothTransition,
-            Shape::SmoothTransition => Shape::GaussianBump,
+            Shape::SmoothTransition => Shape::Rings(Core::Exponential),
+            Shape::Rings(Core::Exponential) => Shape::Rings(Core::Polynomial),
+            Shape::Rings(Core::Polynomial) => Shape::Rings(Core::Rectangular),
+            Shape::Rings(Core::Rectangular) => Shape::Table,
+            Shape::Table => Shape::GaussianBump,
+            Shape::Expression(_) => Shape::GaussianBump,
         }
     }
     fn previous(&mut self) {
         *self = match self {
-            Shape::GaussianBump => Shape::SmoothTransition,
+            Shape::GaussianBump => Shape::Table,
             Shape::GaussianBumpMulti => Shape::GaussianBump,
             Shape::ExponentialDecay => Shape::GaussianBumpMulti,
             Shape::SmoothTransition => Shape::ExponentialDecay,
+            Shape::Rings(Core::Exponential) => Shape::SmoothTransition,
+            Shape::Rings(Core::Polynomial) => Shape::Rings(Core::Exponential),
+            Shape::Rings(Core::Rectangular) => Shape::Rings(Core::Polynomial),
+            Shape::Table => Shape::Rings(Core::Rectangular),
+            Shape::Expression(_) => Shape::Table,
         }
     }
 }
 impl fmt::Debug for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+impl fmt::Display for Shape {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let l = match self {
             Self::GaussianBump => "Gaussian Bump",
             Self::GaussianBumpMulti => "Gaussian Bump Multi",
             Self::ExponentialDecay => "Exponential Decay",
             Self::SmoothTransition => "Smooth Transition",
+            Self::Rings(c) => return write!(f, "Rings ({})", c),
+            Self::Table => "Table",
+            Self::Expression(e) => return write!(f, "Expression ({})", e),
         };
         write!(f, "{}", l)
     }
 }
-impl fmt::Display for Shape {
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Integrator {
+    #[default]
+    Euler,
+    Midpoint,   // RK2
+    RungeKutta4,
+    Asymptotic, // growth is treated as target state
+    Flow,       // mass conserving, growth is treated as affinity
+}
+
+impl Integrator {
+    // rate of change of every channel over a step from base; rates gives growth of channels
+    // in a state, stage is base moved h steps along given rates. The step itself is
+    // stage(rate, 1), except for flow, which moves matter along the rate instead
+    pub fn rate<B: Backend>(
+        &self, b: &B, base: &State<B>,
+        rates: impl Fn(&State<B>) -> State<B>,
+        stage: impl Fn(&State<B>, f32) -> State<B>
+    ) -> State<B> {
+        match self {
+            Integrator::Euler | Integrator::Flow => rates(base),
+            Integrator::Midpoint => rates(&stage(&rates(base), 0.5)),
+            Integrator::RungeKutta4 => {
+                let k1 = rates(base);
+                let k2 = rates(&stage(&k1, 0.5));
+                let k3 = rates(&stage(&k2, 0.5));
+                let k4 = rates(&stage(&k3, 1.));
+                k1.iter().map(|(c,k)|{
+                    let s = b.add(&b.add(k, &b.scale(&k2[c], 2.)), &b.add(&b.scale(&k3[c], 2.), &k4[c]));
+                    (*c, b.scale(&s, 1. / 6.))
+                }).collect()
+            },
+            // growth is a target state that matter relaxes towards
+            Integrator::Asymptotic => rates(base).iter().map(|(c,r)| (*c, b.sub(&b.clamp(r, 0., 1.), &base[c])) ).collect(),
+        }
+    }
+}
+
+impl Cycle for Integrator {
+    fn next(&mut self) {
+        *self = match self {
+            Integrator::Euler => Integrator::Midpoint,
+            Integrator::Midpoint => Integrator::RungeKutta4,
+            Integrator::RungeKutta4 => Integrator::Asymptotic,
+            Integrator::Asymptotic => Integrator::Flow,
+            Integrator::Flow => Integrator::Euler,
+        }
+    }
+    fn previous(&mut self) {
+        *self = match self {
+            Integrator::Euler => Integrator::Flow,
+            Integrator::Midpoint => Integrator::Euler,
+            Integrator::RungeKutta4 => Integrator::Midpoint,
+            Integrator::Asymptotic => Integrator::RungeKutta4,
+            Integrator::Flow => Integrator::Asymptotic,
+        }
+    }
+}
+impl fmt::Debug for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+impl fmt::Display for Integrator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let l = match self {
-            Self::GaussianBump => "Gaussian Bump",
-            Self::GaussianBumpMulti => "Gaussian Bump Multi",
-            Self::ExponentialDecay => "Exponential Decay",
-            Self::SmoothTransition => "Smooth Transition",
+            Self::Euler => "Euler",
+            Self::Midpoint => "Midpoint (RK2)",
+            Self::RungeKutta4 => "Runge-Kutta 4",
+            Self::Asymptotic => "Asymptotic",
+            Self::Flow => "Flow",
         };
         write!(f, "{}", l)
     }
 }
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Boundary {
+    #[default]
+    Periodic, // torus
+    Zero,     // dead border
+    Reflect,  // mirrored along the edge
+    Clamp,    // edge values repeated
+}
+impl Boundary {
+    #[cfg(feature = "arrayfire")]
+    pub fn border_type(&self) -> BorderType {
+        match self {
+            Boundary::Periodic => BorderType::PERIODIC,
+            Boundary::Zero => BorderType::ZERO,
+            Boundary::Reflect => BorderType::SYMMETRIC,
+            Boundary::Clamp => BorderType::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+impl Cycle for Boundary {
+    fn next(&mut self) {
+        *self = match self {
+            Boundary::Periodic => Boundary::Zero,
+            Boundary::Zero => Boundary::Reflect,
+            Boundary::Reflect => Boundary::Clamp,
+            Boundary::Clamp => Boundary::Periodic,
+        }
+    }
+    fn previous(&mut self) {
+        *self = match self {
+            Boundary::Periodic => Boundary::Clamp,
+            Boundary::Zero => Boundary::Periodic,
+            Boundary::Reflect => Boundary::Zero,
+            Boundary::Clamp => Boundary::Reflect,
+        }
+    }
+}
+impl fmt::Debug for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Self::Periodic => "Periodic",
+            Self::Zero => "Zero",
+            Self::Reflect => "Reflect",
+            Self::Clamp => "Clamp",
+        };
+        write!(f, "{}", l)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Mix {
+    #[default]
+    Input,  // sources are summed before convolution
+    Output, // every source is convolved and grown on its own, results are summed
+}
+
+impl Cycle for Mix {
+    fn next(&mut self) {
+        *self = match self {
+            Mix::Input => Mix::Output,
+            Mix::Output => Mix::Input,
+        }
+    }
+    fn previous(&mut self) { self.next() }
+}
+impl fmt::Debug for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Self::Input => "Input",
+            Self::Output => "Output",
+        };
+        write!(f, "{}", l)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Function {
     pub shape: Shape,
     pub centering: bool,  // should it be centered at x (moved down)
     pub parameters: Vec<f32>,
     pub hard_clip: bool,
+    #[serde(skip)]
+    formula: Option<Formula>, // expression shape cycled away from, comes back on the way around
+}
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.shape == other.shape &&
+        self.centering == other.centering &&
+        self.parameters == other.parameters &&
+        self.hard_clip == other.hard_clip
+    }
+}
+// shapes in order of Shape, expression is in between Table and Gaussian Bump once the
+// function had one
+impl Cycle for Function {
+    fn next(&mut self) {
+        match &self.shape {
+            Shape::Expression(f) => { self.formula = Some(f.clone()); self.shape.next(); },
+            Shape::Table if self.formula.is_some() => self.shape = Shape::Expression(self.formula.clone().unwrap()),
+            _ => self.shape.next(),
+        }
+    }
+    fn previous(&mut self) {
+        match &self.shape {
+            Shape::Expression(f) => { self.formula = Some(f.clone()); self.shape.previous(); },
+            Shape::GaussianBump if self.formula.is_some() => self.shape = Shape::Expression(self.formula.clone().unwrap()),
+            _ => self.shape.previous(),
+        }
+    }
 }
 impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -92,16 +338,54 @@ impl Function {
             shape,
             centering,
             parameters,
-            hard_clip
+            hard_clip,
+            formula: None,
         }
     }
 
+    // parameters that calc functions can't work with
+    pub fn check(&self) -> Result<(), String> {
+        let n = self.parameters.len();
+        match &self.shape {
+            Shape::GaussianBumpMulti if n == 0 || !n.is_multiple_of(3) => 
+                Err(format!("{} needs parameters in triples, got {}", self.shape, n)),
+            Shape::GaussianBumpMulti => Ok(()),
+            Shape::Rings(_) if n == 0 => Err(format!("{} needs at least 1 ring", self.shape)),
+            Shape::Rings(_) => Ok(()),
+            Shape::Table if n < 4 || !n.is_multiple_of(2) =>
+                Err(format!("{} needs at least 2 (x, y) points, got {} values", self.shape, n)),
+            Shape::Table if self.parameters.chunks(2).tuple_windows().any(|(a, b)| a[0] >= b[0] ) =>
+                Err(format!("{} x values have to be ascending", self.shape)),
+            Shape::Table => Ok(()),
+            Shape::Expression(e) => match &e.parsed {
+                Err(reason) => Err(format!("{}: {}", self.shape, reason)),
+                Ok(_) if n < e.names.len() =>
+                    Err(format!("{} needs {} parameters, got {}", self.shape, e.names.len(), n)),
+                Ok(_) => Ok(()),
+            },
+            _ if n < 2 => Err(format!("{} needs 2 parameters, got {}", self.shape, n)),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "arrayfire")]
     pub fn calc_array(&self, x: &Array<f32>) -> Array<f32> {
+        self.calc_array_mapped(x, &HashMap::new())
+    }
+    // parameters present in maps vary across space instead of being constant
+    #[cfg(feature = "arrayfire")]
+    pub fn calc_array_mapped(&self, x: &Array<f32>, maps: &HashMap<usize, Array<f32>>) -> Array<f32> {
+        let p = |i: usize| -> Array<f32> {
+            match maps.get(&i) {
+                Some(m) => m.clone(),
+                None => constant(self.parameters[i], x.dims()),
+            }
+        };
         // 0 - width, 1 - offset
-        let mut y = match self.shape {
+        let mut y = match &self.shape {
             Shape::GaussianBump => {
-                let mut t = x - self.parameters[1];
-                t = &t / self.parameters[0];
+                let mut t = x - p(1);
+                t = &t / p(0);
                 t = &t * &t;
                 t = &(-t) / 2_f32;
                 t = exp(&t);
@@ -109,31 +393,57 @@ impl Function {
             },
             Shape::GaussianBumpMulti => {
                 let mut base = constant(0_f32, x.dims());
-                self.parameters.chunks(3).for_each(|p| {
-                    let mut t = x - p[1];
-                    t = &t / p[0];
+                (0..self.parameters.len() / 3).for_each(|i| {
+                    let mut t = x - p(i*3+1);
+                    t = &t / p(i*3);
                     t = &t * &t;
                     t = &(-t) / 2_f32;
-                    t = exp(&t) * p[2];
+                    t = exp(&t) * p(i*3+2);
                     base = &base + &t;
                 } );
                 base
             },
             Shape::ExponentialDecay => {
-                let mut t = x - self.parameters[1];
-                t = &t / self.parameters[0];
+                let mut t = x - p(1);
+                t = &t / p(0);
                 t = exp(&(-t));
                 t = clamp(&t, &0_f32, &1_f32, false);
                 t
             },
             Shape::SmoothTransition => {
-                let mut t = x - self.parameters[1];
-                t = &t / self.parameters[0];
+                let mut t = x - p(1);
+                t = &t / p(0);
                 t = exp(&t);
                 t = &t + 1_f32;
                 t = 1_f32 / &t;
                 t
             },
+            Shape::Rings(core) => {
+                // x in 0..1 is split into equal rings, each scaled by its height
+                let t = x * self.parameters.len() as f32;
+                let ring = floor(&t);
+                let mut base = constant(0_f32, x.dims());
+                (0..self.parameters.len()).for_each(|i| {
+                    let inside = eq(&ring, &(i as f32), false).cast::<f32>();
+                    base = &base + inside * p(i);
+                });
+                base * core.calc_array(&(&t - &ring))
+            },
+            Shape::Table => {
+                // sum of ramps, each one rising between neighbouring points
+                let mut base = p(1);
+                (0..self.parameters.len() / 2 - 1).for_each(|i| {
+                    let width = p(i*2+2) - p(i*2);
+                    let slope = (p(i*2+3) - p(i*2+1)) / &width;
+                    let t = clamp(&(x - p(i*2)), &0_f32, &f32::INFINITY, false);
+                    base = &base + minof(&t, &width, false) * slope;
+                });
+                base
+            },
+            Shape::Expression(e) => match &e.parsed {
+                Ok(expr) => expr.eval_array(x, &p),
+                Err(_) => constant(0_f32, x.dims()), // reported by check
+            },
         };
 
         y = if self.hard_clip {clamp( &y, &0_f32, &1_f32, false)} else {
@@ -149,18 +459,23 @@ impl Function {
         y
     }
     pub fn _calc(&self, x: f32) -> f32 {
+        self.calc_with(x, &self.parameters)
+    }
+    // with other parameters, e.g. of single cell of parameter maps
+    pub fn calc_with(&self, x: f32, parameters: &[f32]) -> f32 {
+        let p = parameters;
         // 0 - width, 1 - offset
-        let mut y = match self.shape {
+        let mut y = match &self.shape {
             Shape::GaussianBump => {
-                let mut t = x - self.parameters[1];
-                t = t / self.parameters[0];
+                let mut t = x - p[1];
+                t = t / p[0];
                 t = t * t;
                 t = (-t) / 2_f32;
                 t = t.exp();
                 t
             },
             Shape::GaussianBumpMulti => {
-                self.parameters.chunks(3).map(|p| {
+                p.chunks(3).map(|p| {
                     let mut t = x - p[1];
                     t = t / p[0];
                     t = t * t;
@@ -170,20 +485,38 @@ impl Function {
                 } ).sum()
             },
             Shape::ExponentialDecay => { // comes from infinity, so have to be clamped
-                let mut t = x - self.parameters[1];
-                t = t / self.parameters[0];
+                let mut t = x - p[1];
+                t = t / p[0];
                 t = (-t).exp();
                 t = t.clamp(0_f32, 1_f32);
                 t
             },
             Shape::SmoothTransition => {
-                let mut t = x - self.parameters[1];
-                t = t / self.parameters[0];
+                let mut t = x - p[1];
+                t = t / p[0];
                 t = t.exp();
                 t = t + 1_f32;
                 t = 1_f32 / t;
                 t
             },
+            Shape::Rings(core) => {
+                let t = x * p.len() as f32;
+                let ring = t.floor();
+                if ring < 0. || ring as usize >= p.len() { 0. } else {
+                    p[ring as usize] * core._calc(t - ring)
+                }
+            },
+            Shape::Table => {
+                (0..p.len() / 2 - 1).map(|i| {
+                    let width = p[i*2+2] - p[i*2];
+                    let slope = (p[i*2+3] - p[i*2+1]) / width;
+                    (x - p[i*2]).clamp(0., width) * slope
+                }).sum::<f32>() + p[1]
+            },
+            Shape::Expression(e) => match &e.parsed {
+                Ok(expr) => expr.eval(x, p),
+                Err(_) => 0.,
+            },
         };
 
         y = if self.hard_clip {y.clamp(0., 1.)} else {
@@ -0,0 +1,42 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::Backend;
+
+
+
+// random perturbations of the update, reproducible with seed of the world
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Noise {
+    pub growth: f32,  // std of gaussian noise added to growth
+    pub update: f32,  // chance that cell skips an update (asynchronous update)
+    pub dropout: f32, // chance that cell is zeroed after an update
+}
+impl Noise {
+    pub fn growth<B: Backend>(&self, b: &B, out: &B::Matrix, random: &B::Random) -> B::Matrix {
+        if self.growth <= 0. { return out.clone() }
+        b.add(out, &b.scale(&b.normal(random, b.dims(out)), self.growth))
+    }
+
+    // old - state before the update, new - state after it
+    pub fn update<B: Backend>(&self, b: &B, old: &B::Matrix, new: &B::Matrix, random: &B::Random) -> B::Matrix {
+        let mut t = new.clone();
+        if self.update > 0. {
+            let keep = b.step(&b.uniform(random, b.dims(&t)), self.update);
+            t = b.add(&b.mul(&t, &keep), &b.sub(old, &b.mul(old, &keep)));
+        }
+        if self.dropout > 0. {
+            let keep = b.step(&b.uniform(random, b.dims(&t)), self.dropout);
+            t = b.mul(&t, &keep);
+        }
+        t
+    }
+
+    pub fn changes_update(&self) -> bool {
+        self.update > 0. || self.dropout > 0.
+    }
+}
+impl fmt::Debug for Noise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "G={}, U={}, D={}", self.growth, self.update, self.dropout)
+    }
+}
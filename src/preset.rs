@@ -0,0 +1,133 @@
+use std::{env, fmt, fs, io};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+
+
+// what went wrong with a preset file
+pub enum Cause {
+    Io(io::Error),
+    Toml(String),            // malformed settings
+    Bincode(bincode::Error), // malformed matrix
+    Key(String),             // file name that isn't a numeric key
+    Missing(String),         // part that preset refers to but doesn't contain
+    Unsupported(String),
+    Occupied,                // directory holds something else than a preset
+    Invalid(String),         // settings that can't work together
+}
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Toml(e) => write!(f, "invalid toml: {}", e.trim()),
+            Self::Bincode(e) => write!(f, "invalid matrix: {}", e),
+            Self::Key(name) => write!(f, "'{}' isn't a numeric key", name),
+            Self::Missing(what) => write!(f, "{} is missing", what),
+            Self::Unsupported(what) => write!(f, "{} isn't supported", what),
+            Self::Occupied => write!(f, "directory isn't empty and has no lenia.toml"),
+            Self::Invalid(what) => write!(f, "{}", what),
+        }
+    }
+}
+
+impl fmt::Debug for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// failed load or save, path - file or directory it happened on
+pub struct PresetError {
+    pub path: String,
+    pub cause: Cause,
+}
+impl PresetError {
+    pub fn new(path: &str, cause: Cause) -> Self {
+        Self { path: path.to_string(), cause }
+    }
+}
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.cause)
+    }
+}
+impl fmt::Debug for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+impl std::error::Error for PresetError {}
+
+// directory of numbered presets, <root>/<key>
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Presets {
+    pub root: String,
+}
+impl Presets {
+    pub fn new(root: &str) -> Self {
+        Self { root: root.trim_end_matches('/').to_string() }
+    }
+    // LENIA_PRESETS or "data"
+    pub fn from_env() -> Self {
+        Self::new(&env::var("LENIA_PRESETS").unwrap_or("data".to_string()))
+    }
+
+    pub fn path(&self, key: usize) -> String {
+        format!("{}/{}", self.root, key)
+    }
+    // key under root or any directory
+    pub fn resolve(&self, preset: &str) -> String {
+        match preset.parse::<usize>() {
+            Ok(key) => self.path(key),
+            Err(_) => preset.to_string(),
+        }
+    }
+
+    // numbered presets that exist, sorted
+    pub fn keys(&self) -> Vec<usize> {
+        let Ok(dir) = fs::read_dir(&self.root) else { return vec![] };
+        let mut keys = dir.filter_map(|e| e.ok() ).filter(|e| e.path().is_dir() )
+            .filter_map(|e| e.file_name().to_str()?.trim().parse::<usize>().ok() ).collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+    pub fn free_key(&self) -> usize {
+        let keys = self.keys();
+        (0..).find(|k| !keys.contains(k) ).unwrap()
+    }
+}
+
+pub(crate) fn read_toml<T: DeserializeOwned>(path: &str) -> Result<T, PresetError> {
+    let s = fs::read_to_string(path).map_err(|e| PresetError::new(path, Cause::Io(e)) )?;
+    toml::from_str(&s).map_err(|e| PresetError::new(path, Cause::Toml(e.to_string())) )
+}
+pub(crate) fn write_toml<T: Serialize>(path: &str, value: &T) -> Result<(), PresetError> {
+    let s = toml::to_string(value).map_err(|e| PresetError::new(path, Cause::Toml(e.to_string())) )?;
+    fs::write(path, s).map_err(|e| PresetError::new(path, Cause::Io(e)) )
+}
+
+pub(crate) fn read_bin<T: DeserializeOwned>(path: &str) -> Result<T, PresetError> {
+    let buffer = fs::read(path).map_err(|e| PresetError::new(path, Cause::Io(e)) )?;
+    bincode::deserialize(&buffer).map_err(|e| PresetError::new(path, Cause::Bincode(e)) )
+}
+pub(crate) fn write_bin<T: Serialize>(path: &str, value: &T) -> Result<(), PresetError> {
+    let buffer = bincode::serialize(value).map_err(|e| PresetError::new(path, Cause::Bincode(e)) )?;
+    fs::write(path, buffer).map_err(|e| PresetError::new(path, Cause::Io(e)) )
+}
+
+pub(crate) fn create_dir(path: &str) -> Result<(), PresetError> {
+    fs::create_dir_all(path).map_err(|e| PresetError::new(path, Cause::Io(e)) )
+}
+
+// files of directory with given extension, by their key, sorted
+pub(crate) fn entries(dir: &str, extension: &str) -> Result<Vec<(usize, String)>, PresetError> {
+    let mut v = vec![];
+    for e in fs::read_dir(dir).map_err(|e| PresetError::new(dir, Cause::Io(e)) )? {
+        let name = e.map_err(|e| PresetError::new(dir, Cause::Io(e)) )?.file_name().to_string_lossy().to_string();
+        let Some(key) = name.strip_suffix(extension) else { continue };
+        let key = key.parse::<usize>().map_err(|_| PresetError::new(dir, Cause::Key(name.clone())) )?;
+        v.push((key, format!("{}/{}", dir.trim_end_matches('/'), name)));
+    }
+    v.sort();
+    Ok(v)
+}
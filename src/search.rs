@@ -0,0 +1,145 @@
+use std::fmt;
+use itertools::Itertools;
+use crate::{Backend, Lenia, Outcome};
+
+
+
+// what search is looking for, every step is scored and scores are averaged over the run
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fitness {
+    #[default]
+    Mean,  // mean of channels, Lenia::fitness
+    Life,  // world neither died nor filled up (0 < fitness < 0.25)
+    Speed, // mean centroid speed of living worlds, looks for gliders
+}
+impl Fitness {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "mean" => Fitness::Mean,
+            "life" => Fitness::Life,
+            "speed" => Fitness::Speed,
+            _ => return None,
+        })
+    }
+
+    // score of world after a step
+    pub fn score<B: Backend>(&self, lenia: &Lenia<B>) -> f32 {
+        let alive = Outcome::of(lenia) == Outcome::Alive;
+        match self {
+            Fitness::Mean => lenia.fitness,
+            Fitness::Life => if alive { 1. } else { 0. },
+            Fitness::Speed if !alive => 0.,
+            Fitness::Speed => {
+                let stats = &lenia.analytics.channels;
+                stats.values().map(|s| s.speed() ).sum::<f32>() / stats.len().max(1) as f32
+            },
+        }
+    }
+}
+impl fmt::Display for Fitness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Self::Mean => "mean",
+            Self::Life => "life",
+            Self::Speed => "speed",
+        };
+        write!(f, "{}", l)
+    }
+}
+impl fmt::Debug for Fitness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// Evolutionary search, candidates are mutations of starting preset (function parameters,
+// radii and channel weights) and every one of them starts from its initial matrices
+pub struct Search<B: Backend> {
+    pub population: Vec<Lenia<B>>, // sorted by score after evaluate()
+    pub scores: Vec<f32>,
+    pub fitness: Fitness,
+    pub steps: usize,     // evaluated steps of every candidate
+    pub rate: f32,        // mutation strength, relative to mutated value
+    pub survivors: usize, // best candidates kept between generations
+    pub generation: usize,
+    engine: B::Random,
+}
+impl<B: Backend> Search<B> {
+    pub fn new(origin: Lenia<B>, size: usize, fitness: Fitness, steps: usize, seed: u64) -> Self {
+        let mut search = Self {
+            population: vec![],
+            scores: vec![],
+            fitness, steps,
+            rate: 0.1,
+            survivors: (size / 4).max(1),
+            generation: 0,
+            engine: origin.backend.random(seed),
+        };
+        search.population = (1..size).map(|_| search.mutate(&origin) ).collect();
+        search.population.insert(0, origin);
+        search
+    }
+
+    // runs every candidate and sorts population by score, best first
+    pub fn evaluate(&mut self) {
+        let scored = self.population.drain(..).map(|genome|{
+            let score = run(&genome, self.fitness, self.steps);
+            (genome, score)
+        }).sorted_by(|a, b| b.1.total_cmp(&a.1) ).collect::<Vec<_>>();
+        (self.population, self.scores) = scored.into_iter().unzip();
+    }
+
+    // survivors stay, rest of population is replaced by their mutations
+    pub fn breed(&mut self) {
+        let size = self.population.len();
+        let survivors = self.survivors.min(size);
+        self.population.truncate(survivors);
+        self.scores.truncate(survivors);
+        (survivors..size).for_each(|i|{
+            let child = self.mutate(&self.population[i % survivors]);
+            self.population.push(child);
+        });
+        self.generation += 1;
+    }
+
+    // best candidates with their scores
+    pub fn best(&self) -> impl Iterator<Item = (&Lenia<B>, f32)> {
+        self.population.iter().zip(self.scores.iter().copied()).take(self.survivors)
+    }
+
+    // mutations that break the preset are dropped, parent value is kept instead
+    fn mutate(&self, parent: &Lenia<B>) -> Lenia<B> {
+        let mut child = parent.clone();
+        let genes = child.layers.values().map(|l| l.kernel.parameters.len() + l.growth_map.parameters.len() + 1 ).sum::<usize>()
+            + child.channels.values().map(|ch| ch.weights.len() ).sum::<usize>();
+        let b = &child.backend;
+        let noise = b.host(&b.normal(&self.engine, [genes.max(1), 1, 1]));
+        let mut noise = noise.into_iter().map(|n| n * self.rate );
+
+        child.layers.iter_mut().sorted_by_key(|(k,_)| **k ).for_each(|(_,l)|{
+            [&mut l.kernel, &mut l.growth_map].into_iter().for_each(|f|{
+                let old = f.clone();
+                f.parameters.iter_mut().for_each(|p| *p += noise.next().unwrap() * p.abs().max(0.01) );
+                if f.check().is_err() { *f = old; }
+            });
+            let r = l.radius as f32 * (1. + noise.next().unwrap());
+            l.radius = (r.round() as usize).max(1);
+        });
+        child.channels.iter_mut().sorted_by_key(|(k,_)| **k ).for_each(|(_,ch)|{
+            ch.weights.iter_mut().sorted_by_key(|(k,_)| **k ).for_each(|(_,w)|{
+                *w += noise.next().unwrap() * w.abs().max(0.1);
+            });
+        });
+
+        if child.validate().is_empty() { child } else { parent.clone() }
+    }
+}
+
+// average score of single candidate, invalid one scores 0
+fn run<B: Backend>(genome: &Lenia<B>, fitness: Fitness, steps: usize) -> f32 {
+    let mut total = 0.;
+    match genome.clone().run(steps, |world| total += fitness.score(world) ) {
+        Ok(_) => total / steps.max(1) as f32,
+        Err(_) => 0.,
+    }
+}
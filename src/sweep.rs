@@ -0,0 +1,141 @@
+use std::fmt;
+use itertools::Itertools;
+use crate::{Backend, Lenia};
+
+
+
+// state of the world judged by mean of channels (Lenia::fitness)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Dead,
+    Alive,
+    Full,
+}
+impl Outcome {
+    pub fn of<B: Backend>(lenia: &Lenia<B>) -> Self {
+        if lenia.fitness <= 0. { Outcome::Dead }
+        else if lenia.fitness >= 0.25 { Outcome::Full }
+        else { Outcome::Alive }
+    }
+}
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = match self {
+            Self::Dead => "dead",
+            Self::Alive => "alive",
+            Self::Full => "full",
+        };
+        write!(f, "{}", l)
+    }
+}
+impl fmt::Debug for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// swept setting, written as "delta", "<layer>.kernel.<parameter>", "<layer>.growth.<parameter>",
+// "<layer>.radius" or "<channel>.weight.<layer>"
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Delta,
+    Kernel { layer: usize, parameter: usize },
+    Growth { layer: usize, parameter: usize },
+    Radius { layer: usize },
+    Weight { channel: usize, layer: usize },
+}
+impl Target {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let parts = name.split('.').collect::<Vec<_>>();
+        let number = |i: usize| parts[i].parse::<usize>().map_err(|_| format!("'{}' in {} isn't a key", parts[i], name) );
+        match parts.as_slice() {
+            ["delta"] => Ok(Target::Delta),
+            [_, "kernel", _] => Ok(Target::Kernel { layer: number(0)?, parameter: number(2)? }),
+            [_, "growth", _] => Ok(Target::Growth { layer: number(0)?, parameter: number(2)? }),
+            [_, "radius"] => Ok(Target::Radius { layer: number(0)? }),
+            [_, "weight", _] => Ok(Target::Weight { channel: number(0)?, layer: number(2)? }),
+            _ => Err(format!("unknown target {}", name)),
+        }
+    }
+
+    pub fn apply<B: Backend>(&self, lenia: &mut Lenia<B>, value: f32) -> Result<(), String> {
+        let missing = || format!("{} doesn't exist", self);
+        match *self {
+            Target::Delta => lenia.delta = value,
+            Target::Kernel { layer, parameter } => {
+                let l = lenia.layers.get_mut(&layer).ok_or_else(missing)?;
+                *l.kernel.parameters.get_mut(parameter).ok_or_else(missing)? = value;
+            },
+            Target::Growth { layer, parameter } => {
+                let l = lenia.layers.get_mut(&layer).ok_or_else(missing)?;
+                *l.growth_map.parameters.get_mut(parameter).ok_or_else(missing)? = value;
+            },
+            Target::Radius { layer } => {
+                lenia.layers.get_mut(&layer).ok_or_else(missing)?.radius = (value.round() as usize).max(1);
+            },
+            Target::Weight { channel, layer } => {
+                let ch = lenia.channels.get_mut(&channel).ok_or_else(missing)?;
+                *ch.weights.get_mut(&layer).ok_or_else(missing)? = value;
+            },
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Delta => write!(f, "delta"),
+            Self::Kernel { layer, parameter } => write!(f, "{}.kernel.{}", layer, parameter),
+            Self::Growth { layer, parameter } => write!(f, "{}.growth.{}", layer, parameter),
+            Self::Radius { layer } => write!(f, "{}.radius", layer),
+            Self::Weight { channel, layer } => write!(f, "{}.weight.{}", channel, layer),
+        }
+    }
+}
+
+// target with its values, "0.growth.0=0.005..0.02:4" (4 evenly spaced) or "0.radius=13,26,52"
+#[derive(Clone, PartialEq)]
+pub struct Axis {
+    pub target: Target,
+    pub values: Vec<f32>,
+}
+impl Axis {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, values) = spec.split_once('=').ok_or(format!("{} has no '='", spec))?;
+        let target = Target::parse(name)?;
+        let float = |s: &str| s.trim().parse::<f32>().map_err(|_| format!("'{}' in {} isn't a number", s, spec) );
+        let values = match values.split_once("..") {
+            Some((from, rest)) => {
+                let (to, n) = rest.split_once(':').ok_or(format!("{} range has no ':<count>'", spec))?;
+                let (from, to) = (float(from)?, float(to)?);
+                let n = n.trim().parse::<usize>().map_err(|_| format!("'{}' in {} isn't a count", n, spec) )?;
+                if n < 2 { vec![from] } else {
+                    (0..n).map(|i| from + (to - from) * i as f32 / (n - 1) as f32 ).collect()
+                }
+            },
+            None => values.split(',').map(float).collect::<Result<_, _>>()?,
+        };
+        Ok(Axis { target, values })
+    }
+}
+
+// every combination of axes values is run from base preset for given number of steps
+pub struct Sweep<B: Backend> {
+    pub base: Lenia<B>,
+    pub axes: Vec<Axis>,
+    pub steps: usize,
+}
+impl<B: Backend> Sweep<B> {
+    // values in order of axes
+    pub fn points(&self) -> Vec<Vec<f32>> {
+        if self.axes.is_empty() { return vec![vec![]] }
+        self.axes.iter().map(|a| a.values.iter().copied() ).multi_cartesian_product().collect()
+    }
+
+    // world after the run, base matrices stay untouched
+    pub fn run(&self, point: &[f32]) -> Result<Lenia<B>, String> {
+        let mut world = self.base.clone();
+        self.axes.iter().zip(point.iter()).try_for_each(|(a,v)| a.target.apply(&mut world, *v) )?;
+        world.run(self.steps, |_| {} ).map_err(|problems| problems.iter().join(", ") )
+    }
+}
@@ -0,0 +1,197 @@
+use std::{cmp::Reverse, collections::HashMap, f32::consts::PI, fmt};
+#[cfg(feature = "arrayfire")]
+use arrayfire::*;
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+
+
+
+// connected cells above threshold, neighbours touch also diagonally and across world edges
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Creature {
+    pub id: usize, // stable between steps
+    pub cells: usize,
+    pub mass: f32,
+    pub centroid: [f32; 3], // on torus, x y z
+}
+impl fmt::Debug for Creature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} N={}, M={}, C={:?}", self.id, self.cells, self.mass, self.centroid)
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Birth(usize),
+    Death(usize),
+    Split { parent: usize, children: Vec<usize> }, // parent id lives on in one of children
+    Merge { parents: Vec<usize>, child: usize },   // child keeps id of one of parents
+}
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Birth(id) => write!(f, "#{} born", id),
+            Self::Death(id) => write!(f, "#{} died", id),
+            Self::Split { parent, children } => write!(f, "#{} split into {:?}", parent, children),
+            Self::Merge { parents, child } => write!(f, "{:?} merged into #{}", parents, child),
+        }
+    }
+}
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// label of every cell (0 - background, components from 1) and number of components
+#[cfg(feature = "arrayfire")]
+pub fn segment(matrix: &Array<f32>, threshold: f32) -> (Vec<usize>, usize) {
+    let mut values = vec![0_f32; matrix.elements()];
+    matrix.host(&mut values);
+    let d = matrix.dims();
+    label(&values, [d[0] as usize, d[1] as usize, d[2] as usize], threshold)
+}
+
+// same on host values, x fastest
+pub fn label(values: &[f32], dims: [usize; 3], threshold: f32) -> (Vec<usize>, usize) {
+    let size = dims.map(|n| n as i64 );
+    let axes = if size[2] > 1 { 3 } else { 2 };
+    // half of neighbourhood, the other half is covered from the neighbour side
+    let offsets = (0..axes).map(|_| -1..=1_i64 ).multi_cartesian_product()
+        .filter(|o| o.iter().find(|v| **v != 0 ) == Some(&1) ).collect::<Vec<_>>();
+
+    let mut parent = (0..values.len()).collect::<Vec<_>>();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    (0..values.len()).filter(|i| values[*i] > threshold ).for_each(|i|{
+        let at = [i as i64 % size[0], i as i64 / size[0] % size[1], i as i64 / (size[0] * size[1])];
+        offsets.iter().for_each(|o|{
+            let mut n = 0;
+            (0..3).rev().for_each(|a|{
+                let v = (at[a] + o.get(a).copied().unwrap_or(0)).rem_euclid(size[a]);
+                n = n * size[a] + v;
+            });
+            let n = n as usize;
+            if values[n] <= threshold { return }
+            let (a, b) = (root(&mut parent, i), root(&mut parent, n));
+            if a != b { parent[a.max(b)] = a.min(b); }
+        });
+    });
+
+    let mut numbers: HashMap<usize, usize> = HashMap::new();
+    let labels = (0..values.len()).map(|i|{
+        if values[i] <= threshold { return 0 }
+        let r = root(&mut parent, i);
+        let next = numbers.len() + 1;
+        *numbers.entry(r).or_insert(next)
+    }).collect();
+    (labels, numbers.len())
+}
+
+// follows creatures of single channel, components are matched by overlapping cells,
+// so creature can't move further than its own size in one step
+pub struct Tracker {
+    pub threshold: f32,
+    pub creatures: Vec<Creature>,
+    pub events: Vec<Event>, // of the last update
+    ids: Vec<usize>, // creature id of every cell, 0 - background
+    next_id: usize,
+}
+impl Tracker {
+    pub fn new(threshold: f32) -> Self {
+        Self { threshold, creatures: vec![], events: vec![], ids: vec![], next_id: 1 }
+    }
+
+    #[cfg(feature = "arrayfire")]
+    pub fn update(&mut self, matrix: &Array<f32>) -> &[Event] {
+        let mut values = vec![0_f32; matrix.elements()];
+        matrix.host(&mut values);
+        let d = matrix.dims();
+        self.update_values(&values, [d[0] as usize, d[1] as usize, d[2] as usize])
+    }
+
+    pub fn update_values(&mut self, values: &[f32], size: [usize; 3]) -> &[Event] {
+        let (labels, count) = label(values, size, self.threshold);
+
+        // previous id, current label -> shared cells
+        let mut overlap: HashMap<(usize, usize), usize> = HashMap::new();
+        if self.ids.len() == labels.len() {
+            self.ids.iter().zip(labels.iter()).filter(|(p,c)| **p > 0 && **c > 0 ).for_each(|(p,c)|{
+                *overlap.entry((*p, *c)).or_insert(0) += 1;
+            });
+        }
+        // largest overlap first, on ties older id wins
+        let links = overlap.iter().map(|((p,c),n)| (*n, *p, *c) ).sorted_by_key(|(n,p,c)| (Reverse(*n), *p, *c) ).collect::<Vec<_>>();
+        let mut best_prev: HashMap<usize, usize> = HashMap::new();
+        let mut best_next: HashMap<usize, usize> = HashMap::new();
+        links.iter().for_each(|(_,p,c)|{
+            best_prev.entry(*c).or_insert(*p);
+            best_next.entry(*p).or_insert(*c);
+        });
+
+        // id continues into the current component it overlaps the most
+        let mut id = vec![0; count + 1];
+        (1..=count).for_each(|c|{
+            id[c] = match best_prev.get(&c) {
+                Some(p) if best_next.get(p) == Some(&c) => *p,
+                _ => { self.next_id += 1; self.next_id - 1 },
+            };
+        });
+
+        self.events = vec![];
+        let children = overlap.keys().into_group_map_by(|(p,_)| *p );
+        children.iter().sorted_by_key(|(p,_)| **p ).for_each(|(p,cs)|{
+            if cs.len() > 1 {
+                let children = cs.iter().map(|(_,c)| id[*c] ).sorted().collect();
+                self.events.push(Event::Split { parent: *p, children });
+            }
+        });
+        let parents = overlap.keys().into_group_map_by(|(_,c)| *c );
+        (1..=count).for_each(|c|{
+            match parents.get(&c) {
+                None => self.events.push(Event::Birth(id[c])),
+                Some(ps) if ps.len() > 1 => {
+                    let parents = ps.iter().map(|(p,_)| *p ).sorted().collect();
+                    self.events.push(Event::Merge { parents, child: id[c] });
+                },
+                _ => {},
+            }
+        });
+        self.creatures.iter().filter(|cr| !children.contains_key(&cr.id) ).for_each(|cr|{
+            self.events.push(Event::Death(cr.id));
+        });
+
+        // size, mass and circular mean position of every component
+        let mut cells = vec![0; count + 1];
+        let mut mass = vec![0_f32; count + 1];
+        let mut angles = vec![[(0_f32, 0_f32); 3]; count + 1];
+        labels.iter().enumerate().filter(|(_,l)| **l > 0 ).for_each(|(i,l)|{
+            let v = values[i];
+            cells[*l] += 1;
+            mass[*l] += v;
+            let at = [i % size[0], i / size[0] % size[1], i / (size[0] * size[1])];
+            (0..3).for_each(|a|{
+                let t = at[a] as f32 * 2. * PI / size[a] as f32;
+                angles[*l][a].0 += v * t.sin();
+                angles[*l][a].1 += v * t.cos();
+            });
+        });
+        self.creatures = (1..=count).map(|c|{
+            let mut centroid = [0.; 3];
+            (0..3).filter(|a| size[*a] > 1 ).for_each(|a|{
+                let (s, co) = angles[c][a];
+                let n = size[a] as f32;
+                centroid[a] = (s.atan2(co) * n / (2. * PI)).rem_euclid(n);
+            });
+            Creature { id: id[c], cells: cells[c], mass: mass[c], centroid }
+        }).sorted_by_key(|cr| cr.id ).collect();
+
+        self.ids = labels.iter().map(|l| id[*l] ).collect();
+        &self.events
+    }
+}
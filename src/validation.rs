@@ -0,0 +1,142 @@
+use std::fmt;
+use itertools::Itertools;
+use crate::{Backend, Integrator, Lenia};
+
+
+
+// things that would make evaluation panic or silently misbehave
+#[derive(Clone, PartialEq)]
+pub enum Problem {
+    MissingSource { layer: usize, channel: usize },
+    SourceWeights { layer: usize, sources: usize, weights: usize },
+    MissingLayer { channel: usize, layer: usize },
+    NoInputs { channel: usize },
+    BadFunction { layer: usize, function: &'static str, reason: String },
+    BadDimensions { layer: usize, dimensions: usize },
+    ZeroRadius { layer: usize },
+    DimensionMismatch { layer: usize, dimensions: usize, channel: usize, depth: usize },
+    SizeMismatch { channel: usize, dims: [usize; 3], expected: [usize; 3] },
+    MapSize { layer: usize, parameter: usize, dims: [usize; 3], expected: [usize; 3] },
+    MapIndex { layer: usize, parameter: usize, parameters: usize },
+    WallSize { channel: Option<usize>, dims: [usize; 3], expected: [usize; 3] },
+    FlowNoise,
+    NoChannels,
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingSource { layer, channel } =>
+                write!(f, "layer {} reads from channel {}, which doesn't exist", layer, channel),
+            Self::SourceWeights { layer, sources, weights } =>
+                write!(f, "layer {} has {} sources and {} source weights", layer, sources, weights),
+            Self::MissingLayer { channel, layer } =>
+                write!(f, "channel {} has weight for layer {}, which doesn't exist", channel, layer),
+            Self::NoInputs { channel } =>
+                write!(f, "channel {} has no weights, nothing feeds it", channel),
+            Self::BadFunction { layer, function, reason } =>
+                write!(f, "layer {} {}: {}", layer, function, reason),
+            Self::BadDimensions { layer, dimensions } =>
+                write!(f, "layer {} has {} dimensions, only 2 and 3 are supported", layer, dimensions),
+            Self::ZeroRadius { layer } =>
+                write!(f, "layer {} has radius 0, it needs at least 1", layer),
+            Self::DimensionMismatch { layer, dimensions, channel, depth: 1 } =>
+                write!(f, "layer {} has {} dimensions, but its source channel {} is flat", layer, dimensions, channel),
+            Self::DimensionMismatch { layer, dimensions, channel, depth } =>
+                write!(f, "layer {} has {} dimensions, but its source channel {} is a volume of depth {}", layer, dimensions, channel, depth),
+            Self::SizeMismatch { channel, dims, expected } =>
+                write!(f, "channel {} is {:?}, other channels are {:?}", channel, dims, expected),
+            Self::MapSize { layer, parameter, dims, expected } =>
+                write!(f, "layer {} map of parameter {} is {:?}, world is {:?}", layer, parameter, dims, expected),
+            Self::MapIndex { layer, parameter, parameters } =>
+                write!(f, "layer {} has map of parameter {}, its growth map takes {} parameters", layer, parameter, parameters),
+            Self::WallSize { channel: Some(c), dims, expected } =>
+                write!(f, "walls of channel {} are {:?}, world is {:?}", c, dims, expected),
+            Self::WallSize { channel: None, dims, expected } =>
+                write!(f, "world walls are {:?}, world is {:?}", dims, expected),
+            Self::FlowNoise =>
+                write!(f, "flow conserves mass, noise can't skip or drop updates"),
+            Self::NoChannels =>
+                write!(f, "there are no channels"),
+        }
+    }
+}
+impl fmt::Debug for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl<B: Backend> Lenia<B> {
+    // empty list means that lenia can be evaluated
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let b = &self.backend;
+        let Some(expected) = self.channels.iter().sorted_by_key(|(k,_)| **k ).next().map(|(_,ch)| b.dims(&ch.matrix) )
+            else { return vec![Problem::NoChannels] };
+
+        self.layers.iter().sorted_by_key(|(k,_)| **k ).for_each(|(k,l)|{
+            l.inputs().iter().for_each(|(c,_)|{
+                match self.channels.get(c) {
+                    None => problems.push(Problem::MissingSource { layer: *k, channel: *c }),
+                    // flat layer would convolve volume slice by slice, spherical one a flat world
+                    Some(ch) => {
+                        let depth = b.dims(&ch.matrix)[2];
+                        if (l.dimensions == 3 && depth == 1) || (l.dimensions == 2 && depth > 1) {
+                            problems.push(Problem::DimensionMismatch { layer: *k, dimensions: l.dimensions, channel: *c, depth });
+                        }
+                    },
+                }
+            });
+            if let Err(reason) = l.kernel.check() {
+                problems.push(Problem::BadFunction { layer: *k, function: "kernel", reason });
+            }
+            if let Err(reason) = l.growth_map.check() {
+                problems.push(Problem::BadFunction { layer: *k, function: "growth map", reason });
+            }
+            if l.dimensions != 2 && l.dimensions != 3 {
+                problems.push(Problem::BadDimensions { layer: *k, dimensions: l.dimensions });
+            }
+            if l.radius == 0 {
+                problems.push(Problem::ZeroRadius { layer: *k });
+            }
+            l.parameter_maps.iter().sorted_by_key(|(i,_)| **i ).for_each(|(i,m)|{
+                if b.dims(m) != expected {
+                    problems.push(Problem::MapSize { layer: *k, parameter: *i, dims: b.dims(m), expected });
+                }
+                if *i >= l.growth_map.parameters.len() {
+                    problems.push(Problem::MapIndex { layer: *k, parameter: *i, parameters: l.growth_map.parameters.len() });
+                }
+            });
+        });
+
+        self.channels.iter().sorted_by_key(|(k,_)| **k ).for_each(|(k,ch)|{
+            if ch.weights.is_empty() {
+                problems.push(Problem::NoInputs { channel: *k });
+            }
+            ch.weights.keys().sorted().for_each(|l|{
+                if !self.layers.contains_key(l) {
+                    problems.push(Problem::MissingLayer { channel: *k, layer: *l });
+                }
+            });
+            if b.dims(&ch.matrix) != expected {
+                problems.push(Problem::SizeMismatch { channel: *k, dims: b.dims(&ch.matrix), expected });
+            }
+            if let Some(w) = &ch.walls {
+                if b.dims(w) != expected {
+                    problems.push(Problem::WallSize { channel: Some(*k), dims: b.dims(w), expected });
+                }
+            }
+        });
+
+        if let Some(w) = &self.walls {
+            if b.dims(w) != expected {
+                problems.push(Problem::WallSize { channel: None, dims: b.dims(w), expected });
+            }
+        }
+        if self.integrator == Integrator::Flow && self.noise.changes_update() {
+            problems.push(Problem::FlowNoise);
+        }
+
+        problems
+    }
+}
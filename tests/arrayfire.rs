@@ -0,0 +1,77 @@
+mod common;
+use std::collections::{BTreeMap, HashMap};
+use arrayfire::*;
+use lenia_gpu::{ArrayFire, Backend, Batch, Boundary, Channel, Core, Formula, Function, Lenia, PackageLenia, Shape};
+use common::*;
+
+fn lenia(name: &str, dims: [usize; 3]) -> Lenia<ArrayFire> {
+    let package = package(name);
+    let channels = package.channels.iter().map(|(k,_)|{
+        (*k, Channel::new(ArrayFire.matrix(&pattern(dims, *k), dims)))
+    }).collect();
+    let mut lenia = Lenia::new(package.lenia.delta, channels, HashMap::new());
+    PackageLenia::update_lenia(&package, &mut lenia).unwrap_or_else(|p| panic!("{}: {:?}", name, p) );
+    lenia.init();
+    lenia
+}
+
+#[test]
+fn presets_match_golden() {
+    PRESETS.iter().for_each(|(name, dims, steps)|{
+        let mut lenia = lenia(name, *dims);
+        (0..*steps).for_each(|_|{ lenia.evaluate(); });
+        let channels = lenia.channels.iter().map(|(k,ch)| (*k, ArrayFire.host(&ch.matrix)) ).collect::<BTreeMap<_,_>>();
+        golden(name, &channels);
+    });
+}
+
+#[test]
+fn batch_refuses_worlds_it_cant_stack() {
+    assert!(Batch::new(vec![]).is_err());
+    let mut a = lenia("orbium", [48, 48, 1]);
+    a.layers.values_mut().for_each(|l| l.boundary = Boundary::Zero );
+    let mut b = a.clone();
+    b.layers.values_mut().for_each(|l| l.radius += 2 );
+    // padded worlds need kernels of the same size
+    let e = Batch::new(vec![a.clone(), b]).err().unwrap();
+    assert!(e.contains("padding of layer"), "{}", e);
+    assert!(Batch::new(vec![a.clone(), a]).is_ok());
+}
+
+#[test]
+fn kernel_lookup_sums_to_one() {
+    PRESETS.iter().for_each(|(name, dims, _)|{
+        lenia(name, *dims).layers.iter().for_each(|(k,l)|{
+            let (sum, _) = sum_all(&l.kernel_lookup);
+            assert!((sum - 1.).abs() < 1e-5, "{} layer {} kernel sums to {}", name, k, sum);
+        });
+    });
+}
+
+#[test]
+fn calc_array_matches_calc() {
+    let shapes = [
+        (Shape::GaussianBump, vec![0.15, 0.5]),
+        (Shape::GaussianBumpMulti, vec![0.1, 0.3, 1., 0.1, 0.75, 0.5]),
+        (Shape::ExponentialDecay, vec![0.2, 0.1]),
+        (Shape::SmoothTransition, vec![0.05, 0.4]),
+        (Shape::Rings(Core::Exponential), vec![1., 0.5, 0.25]),
+        (Shape::Rings(Core::Polynomial), vec![1., 0.5]),
+        (Shape::Rings(Core::Rectangular), vec![0.5, 1.]),
+        (Shape::Table, vec![0., 0., 0.4, 1., 1., 0.2]),
+        (Shape::Expression(Formula::new("exp(-((x-m)/s)^2/2)", vec!["m".to_string(), "s".to_string()])), vec![0.3, 0.1]),
+    ];
+    // cell borders of rings are avoided, single ulp decides there which ring x belongs to
+    let x = (0..240).map(|i| i as f32 / 200. + 0.0013 ).collect::<Vec<_>>();
+    let input = Array::new(&x, Dim4::new(&[x.len() as u64, 1, 1, 1]));
+    shapes.iter().for_each(|(shape, parameters)|{
+        [(false, false), (false, true), (true, false), (true, true)].iter().for_each(|(hard_clip, centering)|{
+            let f = Function::new(shape.clone(), *centering, parameters.clone(), *hard_clip);
+            let y = ArrayFire.host(&f.calc_array(&input));
+            x.iter().zip(y.iter()).for_each(|(x,y)|{
+                let expected = f._calc(*x);
+                assert!((expected - y).abs() < 1e-4, "{:?} at {}: {} != {}", f, x, y, expected);
+            });
+        });
+    });
+}
@@ -0,0 +1,71 @@
+#![allow(dead_code)]
+use std::{collections::BTreeMap, env, fs};
+use lenia_gpu::PackageLenia;
+
+// reference presets in tests/presets/<name>, same layout as data/<key> but without matrices,
+// world size and number of steps
+pub const PRESETS: [(&str, [usize; 3], usize); 5] = [
+    ("orbium", [48, 48, 1], 10),
+    ("rings", [40, 36, 1], 10),
+    ("mix", [44, 44, 1], 10),
+    ("aniso", [40, 40, 1], 10),
+    ("volume", [20, 20, 20], 5),
+];
+
+// tolerance of golden comparison, snapshots are written by cpu backend
+pub const TOLERANCE: f32 = 1e-3;
+
+pub fn package(name: &str) -> PackageLenia {
+    let path = format!("tests/presets/{}/", name);
+    let read = |p: String| fs::read_to_string(&p).unwrap_or_else(|e| panic!("{}: {}", p, e) );
+    let keyed = |dir: &str| {
+        let mut v = fs::read_dir(path.clone() + dir).unwrap().map(|e|{
+            let name = e.unwrap().file_name().into_string().unwrap();
+            (name.replace(".toml", "").parse::<usize>().unwrap(), read(path.clone() + dir + "/" + &name))
+        }).collect::<Vec<_>>();
+        v.sort_by_key(|(k,_)| *k );
+        v
+    };
+    PackageLenia {
+        lenia: toml::from_str(&read(path.clone() + "lenia.toml")).unwrap(),
+        layers: keyed("layer").into_iter().map(|(k,s)| (k, toml::from_str(&s).unwrap()) ).collect(),
+        channels: keyed("channel").into_iter().map(|(k,s)| (k, toml::from_str(&s).unwrap()) ).collect(),
+    }
+}
+
+// pseudo random cells in a ball in the middle of the world, different for every channel
+pub fn pattern(dims: [usize; 3], channel: usize) -> Vec<f32> {
+    let mut data = Vec::with_capacity(dims.iter().product());
+    for z in 0..dims[2] {
+        for y in 0..dims[1] {
+            for x in 0..dims[0] {
+                let r = [x, y, z].iter().zip(dims.iter()).filter(|(_,n)| **n > 1 ).map(|(i,n)|{
+                    let t = (*i as f32 - *n as f32 / 2.) / (*n as f32 / 4.);
+                    t * t
+                }).sum::<f32>();
+                let hash = (x * 73856093) ^ (y * 19349663) ^ (z * 83492791) ^ (channel * 2654435761);
+                data.push(if r < 1. { (hash % 1000) as f32 / 1000. } else { 0. });
+            }
+        }
+    }
+    data
+}
+
+// LENIA_BLESS=1 writes current channels as new snapshot instead of comparing
+pub fn golden(name: &str, channels: &BTreeMap<usize, Vec<f32>>) {
+    let path = format!("tests/golden/{}.bin", name);
+    if env::var("LENIA_BLESS").is_ok_and(|v| v == "1") {
+        fs::write(&path, bincode::serialize(channels).unwrap()).unwrap();
+        return
+    }
+    let buffer = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}, run with LENIA_BLESS=1 to create it", path, e) );
+    let expected: BTreeMap<usize, Vec<f32>> = bincode::deserialize(&buffer).unwrap();
+    assert_eq!(expected.keys().collect::<Vec<_>>(), channels.keys().collect::<Vec<_>>(), "{}: channels differ", name);
+    expected.iter().for_each(|(k,e)|{
+        let c = &channels[k];
+        assert_eq!(e.len(), c.len(), "{}: channel {} size differs", name, k);
+        let (i, diff) = e.iter().zip(c.iter()).map(|(a,b)| (a - b).abs() ).map(|d| if d.is_nan() { f32::INFINITY } else { d } ).enumerate()
+            .fold((0, 0.), |m, (i,d)| if d > m.1 { (i, d) } else { m } );
+        assert!(diff <= TOLERANCE, "{}: channel {} cell {} is off by {} ({} expected)", name, k, i, diff, e[i]);
+    });
+}
@@ -0,0 +1,395 @@
+mod common;
+use std::{collections::{BTreeMap, HashMap}, env, fs, process};
+use lenia_gpu::{Anisotropy, Axis, Backend, Cause, Channel, Cpu, Cycle, DataLayer, DataLenia, Event, Expr, Formula, Function, Integrator, Lenia, Noise, PackageLenia, Presets, Problem, Shape, Target, Tracker, label};
+use common::*;
+
+fn lenia(name: &str, dims: [usize; 3]) -> Lenia<Cpu> {
+    let package = package(name);
+    let channels = package.channels.iter().map(|(k,_)|{
+        (*k, Channel::new(Cpu.matrix(&pattern(dims, *k), dims)))
+    }).collect();
+    let mut lenia = Lenia::new(package.lenia.delta, channels, HashMap::new());
+    PackageLenia::update_lenia(&package, &mut lenia).unwrap_or_else(|p| panic!("{}: {:?}", name, p) );
+    lenia.init();
+    lenia
+}
+
+#[test]
+fn presets_match_golden() {
+    PRESETS.iter().for_each(|(name, dims, steps)|{
+        let mut lenia = lenia(name, *dims);
+        (0..*steps).for_each(|_|{ lenia.evaluate(); });
+        let channels = lenia.channels.iter().map(|(k,ch)| (*k, ch.matrix.data.clone()) ).collect::<BTreeMap<_,_>>();
+        golden(name, &channels);
+    });
+}
+
+#[test]
+fn kernels_sum_to_one() {
+    PRESETS.iter().for_each(|(name, dims, _)|{
+        lenia(name, *dims).layers.iter().for_each(|(k,l)|{
+            let sum = Cpu.sum(&l.kernel_lookup);
+            assert!((sum - 1.).abs() < 1e-5, "{} layer {} kernel sums to {}", name, k, sum);
+        });
+    });
+}
+
+#[test]
+fn flow_conserves_mass() {
+    let dims = [48, 48, 1];
+    // one wall column across the creature, walls hold no mass
+    let column = (0..48 * 48).map(|i| if i % 48 == 30 { 1. } else { 0. } ).collect::<Vec<f32>>();
+    [None, Some(Cpu.matrix(&column, dims))].into_iter().for_each(|walls|{
+        let mut lenia = lenia("orbium", dims);
+        lenia.integrator = Integrator::Flow;
+        if let Some(w) = &walls { lenia.channels.values_mut().for_each(|ch| ch.pin(w, 0.) ); }
+        lenia.walls = walls;
+        let mass = |l: &Lenia<Cpu>| l.channels.values().map(|ch| Cpu.sum(&ch.matrix) ).sum::<f32>();
+        let before = mass(&lenia);
+        (0..5).for_each(|_|{ lenia.evaluate(); });
+        assert!((mass(&lenia) - before).abs() < before * 1e-4, "walls {}: {} != {}", lenia.walls.is_some(), mass(&lenia), before);
+    });
+}
+
+#[test]
+fn flow_refuses_noise_that_drops_updates() {
+    let mut lenia = lenia("orbium", [48, 48, 1]);
+    lenia.integrator = Integrator::Flow;
+    lenia.noise = Noise { growth: 0.05, update: 0., dropout: 0. };
+    assert!(lenia.validate().is_empty());
+    lenia.noise.update = 0.1;
+    assert_eq!(lenia.validate(), vec![Problem::FlowNoise]);
+}
+
+#[test]
+fn walls_keep_their_value() {
+    let dims = [48, 48, 1];
+    // square of walls in the middle of the creature
+    let square = (0..48 * 48).map(|i| if (20..28).contains(&(i % 48)) && (20..28).contains(&(i / 48)) { 1. } else { 0. } ).collect::<Vec<f32>>();
+    let walls = Cpu.matrix(&square, dims);
+    let run = |value: f32, opaque: bool| {
+        let mut lenia = lenia("orbium", dims);
+        lenia.channels.values_mut().for_each(|ch| ch.pin(&walls, value) );
+        lenia.walls = Some(walls.clone());
+        (lenia.wall_value, lenia.wall_opaque) = (value, opaque);
+        (0..3).for_each(|_|{ lenia.evaluate(); });
+        lenia.channels[&0].matrix.data.clone()
+    };
+    let opaque = run(1., true);
+    square.iter().zip(opaque.iter()).filter(|(w,_)| **w > 0. ).for_each(|(_,v)| assert_eq!(*v, 1.) );
+    // opaque walls are seen by kernels as empty cells
+    let empty = run(0., false);
+    square.iter().zip(opaque.iter().zip(empty.iter())).filter(|(w,_)| **w == 0. ).for_each(|(_,(a,b))|{
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    });
+    assert_ne!(run(1., false), opaque);
+}
+
+#[test]
+fn parameter_maps_vary_growth() {
+    let dims = [48, 48, 1];
+    let plain = lenia("orbium", dims);
+    let mu = plain.layers[&0].growth_map.parameters[0];
+    let run = |maps: Vec<(usize, f32)>| {
+        let mut lenia = plain.clone();
+        maps.iter().for_each(|(i,v)|{ lenia.layers.get_mut(&0).unwrap().parameter_maps.insert(*i, Cpu.constant(*v, dims)); });
+        (0..3).for_each(|_|{ lenia.evaluate(); });
+        lenia.channels[&0].matrix.data.clone()
+    };
+    // map of the same value everywhere is the parameter itself
+    let expected = run(vec![]);
+    run(vec![(0, mu)]).iter().zip(expected.iter()).for_each(|(a,b)| assert!((a - b).abs() < 1e-6, "{} != {}", a, b) );
+    assert_ne!(run(vec![(0, mu * 1.5)]), expected);
+
+    // map the growth map has no parameter for is reported and left out
+    let mut lenia = plain.clone();
+    lenia.layers.get_mut(&0).unwrap().parameter_maps.insert(2, Cpu.constant(1., dims));
+    assert_eq!(lenia.validate(), vec![Problem::MapIndex { layer: 0, parameter: 2, parameters: 2 }]);
+    assert_eq!(run(vec![(2, 1.)]), expected);
+}
+
+#[test]
+fn zero_radius_is_reported() {
+    let mut lenia = lenia("orbium", [48, 48, 1]);
+    lenia.layers.get_mut(&0).unwrap().radius = 0;
+    assert_eq!(lenia.validate(), vec![Problem::ZeroRadius { layer: 0 }]);
+}
+
+#[test]
+fn rescale_resamples_world_and_kernels() {
+    let mut lenia = lenia("orbium", [48, 48, 1]);
+    let column = (0..48 * 48).map(|i| if i % 48 == 30 { 1. } else { 0. } ).collect::<Vec<f32>>();
+    lenia.walls = Some(Cpu.matrix(&column, [48, 48, 1]));
+    let radius = lenia.layers[&0].radius;
+    lenia.evaluate();
+    let mass = Cpu.sum(&lenia.channels[&0].matrix);
+    lenia.rescale(2., Some(0.05));
+
+    assert_eq!(lenia.channels[&0].matrix.dims, [96, 96, 1]);
+    assert_eq!(lenia.layers[&0].radius, radius * 2);
+    assert_eq!(lenia.delta, 0.05);
+    assert!(lenia.analytics.channels.is_empty());
+    // walls stay walls, pattern keeps its density
+    let walls = lenia.walls.as_ref().unwrap();
+    assert_eq!(walls.dims, [96, 96, 1]);
+    assert!(walls.data.iter().all(|w| *w == 0. || *w == 1. ));
+    assert_eq!(Cpu.sum(walls), 2. * 96.);
+    let scaled = Cpu.sum(&lenia.channels[&0].matrix);
+    assert!((scaled / 4. - mass).abs() < mass * 0.01, "{} != {}", scaled / 4., mass);
+    assert!(lenia.validate().is_empty());
+    lenia.evaluate();
+}
+
+#[test]
+fn noisy_runs_replay_from_seed() {
+    let noisy = || {
+        let mut lenia = lenia("mix", [44, 44, 1]);
+        lenia.noise = Noise { growth: 0.05, update: 0.2, dropout: 0.01 };
+        lenia.reseed(3);
+        lenia
+    };
+    // separate worlds, channels are visited in different order of their maps
+    let mut a = noisy();
+    let mut b = noisy();
+    (0..3).for_each(|_|{ a.evaluate(); b.evaluate(); });
+    a.channels.iter().for_each(|(k,ch)| assert_eq!(ch.matrix.data, b.channels[k].matrix.data, "channel {}", k) );
+
+    // copies and updates that keep the seed go on from where the original got to
+    let mut c = b.clone();
+    PackageLenia::update_lenia(&PackageLenia::from_lenia(&a), &mut a).unwrap();
+    (0..3).for_each(|_|{ a.evaluate(); b.evaluate(); c.evaluate(); });
+    a.channels.iter().for_each(|(k,ch)|{
+        assert_eq!(ch.matrix.data, b.channels[k].matrix.data, "channel {}", k);
+        assert_eq!(b.channels[k].matrix.data, c.channels[k].matrix.data, "channel {}", k);
+    });
+}
+
+#[test]
+fn analytics_are_measured_every_step() {
+    let mut a = lenia("orbium", [48, 48, 1]);
+    a.noise = Noise { growth: 0.05, update: 0., dropout: 0. };
+    let first = a.evaluate();
+    assert_eq!(first.step, 1);
+    assert_eq!(first.channels[&0].velocity, [0.; 3]);
+    (0..3).for_each(|_|{ a.evaluate(); });
+    let previous = a.analytics.clone();
+    let stats = a.evaluate();
+    assert_eq!(stats.step, 5);
+    assert_eq!(a.analytics, stats);
+    // velocity is the centroid shift of the last step only
+    let (s, p) = (stats.channels[&0], previous.channels[&0]);
+    let expected = (s.centroid[0] - p.centroid[0]) / a.delta;
+    assert!((s.velocity[0] - expected).abs() < 1e-4, "{} != {}", s.velocity[0], expected);
+    assert!(s.growth > 0.);
+}
+
+#[test]
+fn lookup_is_symmetric_without_anisotropy() {
+    let (distances, weights) = Anisotropy::default().lookup(5, 2);
+    assert_eq!(distances.len(), 11 * 11);
+    assert!(weights.iter().all(|w| *w == 1.));
+    assert_eq!(distances[5 * 11 + 5], 0.);
+    assert_eq!(distances[5 * 11], 1.);
+    (0..distances.len()).for_each(|i| assert_eq!(distances[i], distances[distances.len() - 1 - i]) );
+}
+
+#[test]
+fn fft_matches_circular_convolution() {
+    // odd sizes aren't powers of two, so they go through Bluestein
+    [[13, 11, 1], [16, 9, 1], [7, 6, 5]].iter().for_each(|dims|{
+        let a = pattern(*dims, 0);
+        let k = [5, 3, if dims[2] > 1 { 3 } else { 1 }];
+        let kernel = (0..k.iter().product::<usize>()).map(|i| (i * 13 % 17) as f32 / 17. + 0.1 ).collect::<Vec<_>>();
+        let fast = Cpu.convolve(&Cpu.matrix(&a, *dims), &Cpu.matrix(&kernel, k));
+
+        // out[x] = sum over kernel cells of a[x - (cell - centre)], wrapping around
+        let at = |p: [usize; 3]| p[0] + dims[0] * (p[1] + dims[1] * p[2]);
+        let mut slow = vec![0_f32; a.len()];
+        (0..a.len()).for_each(|i|{
+            let x = [i % dims[0], i / dims[0] % dims[1], i / (dims[0] * dims[1])];
+            kernel.iter().enumerate().for_each(|(j,w)|{
+                let c = [j % k[0], j / k[0] % k[1], j / (k[0] * k[1])];
+                let p = [0, 1, 2].map(|d| (x[d] as isize - c[d] as isize + (k[d] / 2) as isize).rem_euclid(dims[d] as isize) as usize );
+                slow[i] += w * a[at(p)];
+            });
+        });
+        fast.data.iter().zip(slow.iter()).for_each(|(f,s)| assert!((f - s).abs() < 1e-4, "{:?}: {} != {}", dims, f, s) );
+    });
+}
+
+#[test]
+fn load_reports_missing_directory() {
+    // reference presets have no matrices
+    let e = DataLenia::load::<Cpu>("tests/presets/orbium").err().unwrap();
+    assert_eq!(e.path, "tests/presets/orbium/matrix");
+    assert!(matches!(e.cause, Cause::Io(_)), "{}", e);
+    let e = DataLenia::load::<Cpu>("tests/presets/missing/").err().unwrap();
+    assert_eq!(e.path, "tests/presets/missing/lenia.toml");
+}
+
+#[test]
+fn saved_preset_loads_and_is_validated() {
+    let dir = env::temp_dir().join(format!("lenia_saved_{}", process::id()));
+    let dir = dir.to_str().unwrap();
+    let mut lenia = lenia("mix", [44, 44, 1]);
+    lenia.evaluate();
+    DataLenia::save(dir, &lenia).unwrap();
+    let loaded = DataLenia::load::<Cpu>(dir).unwrap();
+    assert_eq!(loaded, lenia);
+    lenia.channels.iter().for_each(|(k,ch)| assert_eq!(loaded.channels[k].matrix.data, ch.matrix.data) );
+
+    // weight for a layer that isn't there
+    fs::write(format!("{}/channel/0.toml", dir), "keys = [7]\nfloats = [1.0]\n").unwrap();
+    let e = DataLenia::load::<Cpu>(dir).err().unwrap();
+    assert_eq!(e.path, dir);
+    assert!(matches!(&e.cause, Cause::Invalid(p) if p.contains("layer 7")), "{}", e);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn presets_resolve_keys_and_directories() {
+    let presets = Presets::new("tests/presets/");
+    assert_eq!(presets.path(3), "tests/presets/3");
+    assert_eq!(presets.resolve("3"), "tests/presets/3");
+    assert_eq!(presets.resolve("../shared/orbium"), "../shared/orbium");
+    // only numbered directories are presets
+    assert!(presets.keys().is_empty());
+
+    let root = env::temp_dir().join(format!("lenia_presets_{}", process::id()));
+    ["0", "2", "10", "notes"].iter().for_each(|d| fs::create_dir_all(root.join(d)).unwrap() );
+    fs::write(root.join("1"), "not a directory").unwrap();
+    let presets = Presets::new(root.to_str().unwrap());
+    assert_eq!(presets.keys(), vec![0, 2, 10]);
+    assert_eq!(presets.free_key(), 1);
+    fs::remove_dir_all(&root).unwrap();
+    assert!(presets.keys().is_empty());
+    assert_eq!(presets.free_key(), 0);
+}
+
+#[test]
+fn expressions_follow_precedence() {
+    let names = ["m".to_string(), "s".to_string()];
+    [
+        ("1 + 2 * 3", 7.),
+        ("(1 + 2) * 3", 9.),
+        ("1 - 2 - 3", -4.),
+        ("8 / 4 / 2", 1.),
+        ("2 ^ 3 ^ 2", 512.),
+        ("-2 ^ 2", -4.),
+        ("2 * -3", -6.),
+        ("--x", 0.5),
+        ("m * s - x", 5.5),
+        ("exp(0) + sqrt(m * 8)", 5.),
+        ("abs(-s) + floor(x)", 3.),
+        ("1e-3 * 1000", 1.),
+        ("2 * pi", 2. * std::f32::consts::PI),
+    ].iter().for_each(|(source, expected)|{
+        let v = Expr::parse(source, &names).unwrap_or_else(|e| panic!("{}: {}", source, e) ).eval(0.5, &[2., 3.]);
+        assert!((v - expected).abs() < 1e-5, "{} = {}, expected {}", source, v, expected);
+    });
+}
+
+#[test]
+fn expression_errors_fail_preset_loading() {
+    let names = ["m".to_string()];
+    [
+        ("1 +", "unexpected end"),
+        ("(1 + 2", "expected ')'"),
+        ("1 2", "unexpected '2'"),
+        ("q * x", "unknown name 'q'"),
+        ("foo(x)", "unknown function 'foo'"),
+        ("1.2.3", "bad number"),
+    ].iter().for_each(|(source, expected)|{
+        let e = Expr::parse(source, &names).err().unwrap_or_else(|| panic!("{} parsed", source) );
+        assert!(e.contains(expected), "{}: {}", source, e);
+    });
+
+    let layer = r#"
+        source_key = 0
+        radius = 5
+        kernel = { shape = "GaussianBump", centering = false, parameters = [0.15, 0.5], hard_clip = true }
+        growth_map = { shape = { Expression = { source = "exp(-((x-m)/s)^2/2", names = ["m", "s"] } }, centering = true, parameters = [0.15, 0.015], hard_clip = true }
+    "#;
+    let e = toml::from_str::<DataLayer>(layer).err().unwrap();
+    assert!(e.to_string().contains("expected ')'"), "{}", e);
+    let fixed = layer.replace("^2/2\"", "^2/2)\"");
+    assert!(toml::from_str::<DataLayer>(&fixed).is_ok());
+}
+
+#[test]
+fn cycling_shapes_keeps_formula() {
+    let formula = Formula::new("x * m", vec!["m".to_string()]);
+    let mut f = Function::new(Shape::Expression(formula.clone()), false, vec![1.], true);
+    f.next();
+    assert_eq!(f.shape, Shape::GaussianBump);
+    // through 8 built in shapes and back
+    (0..8).for_each(|_| f.next() );
+    assert_eq!(f.shape, Shape::Expression(formula.clone()));
+    f.previous();
+    assert_eq!(f.shape, Shape::Table);
+    (0..8).for_each(|_| f.previous() );
+    assert_eq!(f.shape, Shape::Expression(formula));
+}
+
+#[test]
+fn labels_touch_diagonally_and_across_edges() {
+    // 6x4, x fastest
+    let grid = |rows: [&str; 4]| rows.concat().chars().map(|c| if c == '#' { 1. } else { 0. } ).collect::<Vec<f32>>();
+    let values = grid([
+        "#....#",
+        "......",
+        "..#...",
+        "...#..",
+    ]);
+    let (labels, count) = label(&values, [6, 4, 1], 0.5);
+    assert_eq!(count, 2);
+    // left and right edge are neighbours, and so are the top and bottom row
+    assert_eq!(labels[0], labels[5]);
+    assert_eq!(labels[2 * 6 + 2], labels[3 * 6 + 3]);
+    assert_ne!(labels[0], labels[2 * 6 + 2]);
+    assert!(labels.iter().zip(values.iter()).all(|(l,v)| (*l == 0) == (*v == 0.) ));
+
+    // corners meet across both edges
+    let (_, count) = label(&grid(["#.....", "......", "......", ".....#"]), [6, 4, 1], 0.5);
+    assert_eq!(count, 1);
+    // layers of volume wrap too
+    let mut volume = vec![0.; 3 * 3 * 4];
+    volume[4] = 1.;
+    volume[3 * 9 + 4] = 1.;
+    assert_eq!(label(&volume, [3, 3, 4], 0.5).1, 1);
+}
+
+#[test]
+fn tracker_keeps_identity_through_split_and_merge() {
+    let row = |s: &str| s.chars().map(|c| if c == '#' { 1. } else { 0. } ).collect::<Vec<f32>>();
+    let mut tracker = Tracker::new(0.5);
+    assert_eq!(tracker.update_values(&row("..#######..."), [12, 1, 1]), &[Event::Birth(1)]);
+    // larger part keeps the id
+    assert_eq!(tracker.update_values(&row("..###.##...."), [12, 1, 1]), &[Event::Split { parent: 1, children: vec![1, 2] }]);
+    assert_eq!(tracker.creatures.iter().map(|c| (c.id, c.cells) ).collect::<Vec<_>>(), vec![(1, 3), (2, 2)]);
+    // moving by one cell still overlaps
+    tracker.update_values(&row("...###.##..."), [12, 1, 1]);
+    assert_eq!(tracker.creatures.iter().map(|c| c.id ).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(tracker.update_values(&row("...######..#"), [12, 1, 1]), &[Event::Merge { parents: vec![1, 2], child: 1 }, Event::Birth(3)]);
+    assert_eq!(tracker.update_values(&row("...........#"), [12, 1, 1]), &[Event::Death(1)]);
+    assert!((tracker.creatures[0].centroid[0] - 11.).abs() < 1e-4);
+}
+
+#[test]
+fn sweep_targets_and_axes_parse() {
+    ["delta", "0.kernel.1", "0.growth.0", "1.radius", "2.weight.0"].iter().for_each(|name|{
+        assert_eq!(Target::parse(name).unwrap().to_string(), *name);
+    });
+    ["speed", "0.growth", "a.radius", "0.growth.x", "0.radius.1"].iter().for_each(|name|{
+        assert!(Target::parse(name).is_err(), "{}", name);
+    });
+
+    let axis = Axis::parse("0.growth.0=0.1..0.2:3").unwrap();
+    assert!(axis.target == Target::Growth { layer: 0, parameter: 0 });
+    assert_eq!(axis.values, vec![0.1, 0.15, 0.2]);
+    assert_eq!(Axis::parse("0.radius=13, 26").unwrap().values, vec![13., 26.]);
+    assert_eq!(Axis::parse("delta=0.1..0.5:1").unwrap().values, vec![0.1]);
+    ["delta", "delta=0.1..0.2", "delta=0.1..0.2:x", "delta=0.1,y", "speed=1"].iter().for_each(|spec|{
+        assert!(Axis::parse(spec).is_err(), "{}", spec);
+    });
+}